@@ -0,0 +1,18 @@
+use std::{io, io::Write as _, path::Path};
+
+/// Writes `data` to a temp file next to `path` and renames it into place, so an interrupted run
+/// never leaves a truncated file behind. A temp file left over from a crash isn't in any manifest,
+/// so the next run removes it.
+pub(crate) fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let mut builder = tempfile::Builder::new();
+    builder.prefix(".trunk-compress-").suffix(".tmp");
+    // temp files are private by default, outputs get what `File::create` would give them
+    #[cfg(unix)]
+    builder.permissions(std::os::unix::fs::PermissionsExt::from_mode(0o666));
+    let mut file = builder.tempfile_in(dir)?;
+    file.write_all(data)?;
+    file.as_file().sync_data()?;
+    file.persist(path).map_err(|e| e.error)?;
+    Ok(())
+}
@@ -0,0 +1,250 @@
+use std::{fmt, path::Path};
+
+use globset::{Glob, GlobMatcher};
+use serde::{de, Deserialize, Deserializer, Serialize};
+
+use crate::{
+    summary::{served_size, FileReport},
+    Encoding, Error, Report,
+};
+
+/// A limit on how many bytes the files matching a glob take up in a compressed tree, from
+/// `[[budgets]]` in the config file.
+///
+/// ```toml
+/// [[budgets]]
+/// files = "*_bg.wasm"
+/// max = "1.5 MB"
+///
+/// [[budgets]]
+/// files = "*.js"
+/// encoding = "brotli"
+/// max = "200 KB"
+/// max_increase = "10 KB"
+/// ```
+///
+/// A budget sums up every matching file, so a glob that matches a single file is a budget for
+/// that file. Files served uncompressed, because they're excluded or their compressed variant
+/// didn't pay off, count with their original size.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Budget {
+    /// matched against the path relative to the identity directory, like the include and
+    /// exclude patterns
+    pub files: String,
+    /// the tree the budget applies to, every generated one if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<Encoding>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<ByteSize>,
+    /// how much the files may grow since the baseline, see [`Baseline`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_increase: Option<ByteSize>,
+}
+
+/// A number of bytes, written in the config file either as an integer or as a string with a
+/// unit: `"200 KB"`, `"1.5 MB"`, `"512 KiB"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(transparent)]
+pub struct ByteSize(pub u64);
+
+impl<'de> Deserialize<'de> for ByteSize {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Bytes(u64),
+            Text(String),
+        }
+
+        match Repr::deserialize(deserializer)? {
+            Repr::Bytes(bytes) => Ok(ByteSize(bytes)),
+            Repr::Text(text) => text.parse().map_err(de::Error::custom),
+        }
+    }
+}
+
+impl std::str::FromStr for ByteSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let split = s
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(s.len());
+        let (number, unit) = s.split_at(split);
+        let number: f64 = number
+            .parse()
+            .map_err(|_| format!("invalid size {s:?}, expected e.g. \"200 KB\""))?;
+        let unit = match unit.trim().to_ascii_lowercase().as_str() {
+            "" | "b" => 1,
+            "kb" => 1_000,
+            "mb" => 1_000_000,
+            "gb" => 1_000_000_000,
+            "kib" => 1 << 10,
+            "mib" => 1 << 20,
+            "gib" => 1 << 30,
+            other => return Err(format!("unknown unit {other:?} in {s:?}")),
+        };
+        Ok(ByteSize((number * unit as f64).round() as u64))
+    }
+}
+
+impl fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} bytes", self.0)
+    }
+}
+
+/// A report from an earlier run, as written by `trunk-compress --report json`, to compare
+/// budgets against. Usually committed next to the config file.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Baseline {
+    pub files: Vec<FileReport>,
+}
+
+impl Baseline {
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        let json = std::fs::read(path).map_err(|source| Error::Read {
+            path: path.to_owned(),
+            source,
+        })?;
+        serde_json::from_slice(&json).map_err(|source| Error::Baseline {
+            path: path.to_owned(),
+            source,
+        })
+    }
+}
+
+/// How one [`Budget`] fared in one tree.
+#[derive(Debug, Clone)]
+pub struct BudgetOutcome<'a> {
+    pub budget: &'a Budget,
+    pub encoding: Encoding,
+    pub bytes: u64,
+    /// `None` without a baseline
+    pub baseline_bytes: Option<u64>,
+    /// every matching file with its size now and in the baseline, if it has one there
+    pub files: Vec<(&'a str, u64, Option<u64>)>,
+}
+
+impl BudgetOutcome<'_> {
+    pub fn exceeded(&self) -> bool {
+        let over_max = self.budget.max.is_some_and(|max| self.bytes > max.0);
+        let over_increase = self
+            .budget
+            .max_increase
+            .zip(self.baseline_bytes)
+            .is_some_and(|(max, baseline)| self.bytes > baseline.saturating_add(max.0));
+        over_max || over_increase
+    }
+}
+
+/// One line for the budget, then one per file, with the differences to the baseline.
+impl fmt::Display for BudgetOutcome<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}): {} bytes",
+            self.budget.files, self.encoding, self.bytes
+        )?;
+        if let Some(max) = self.budget.max {
+            write!(f, ", max {max}")?;
+        }
+        if let Some(baseline) = self.baseline_bytes {
+            write!(
+                f,
+                ", {} since the baseline",
+                difference(self.bytes, baseline)
+            )?;
+            if let Some(max) = self.budget.max_increase {
+                write!(f, " (max +{})", max.0)?;
+            }
+        }
+        for (path, bytes, baseline) in &self.files {
+            write!(f, "\n  {path}: {bytes} bytes")?;
+            if let Some(baseline) = baseline {
+                write!(f, " ({})", difference(*bytes, *baseline))?;
+            } else if self.baseline_bytes.is_some() {
+                write!(f, " (new)")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn difference(now: u64, before: u64) -> String {
+    if now >= before {
+        format!("+{}", now - before)
+    } else {
+        format!("-{}", before - now)
+    }
+}
+
+impl Report {
+    /// How every budget fared in every tree it applies to. Files are sized as they are served:
+    /// compressed, or as is when there is no compressed variant. Files of a dry run that would
+    /// be compressed have no size yet and are left out.
+    pub fn check_budgets<'a>(
+        &'a self,
+        budgets: &'a [Budget],
+        baseline: Option<&'a Baseline>,
+    ) -> Result<Vec<BudgetOutcome<'a>>, Error> {
+        let mut encodings: Vec<Encoding> = self.files.iter().filter_map(|f| f.encoding).collect();
+        encodings.sort();
+        encodings.dedup();
+
+        let mut outcomes = Vec::new();
+        for budget in budgets {
+            let glob = Glob::new(&budget.files)
+                .map_err(|source| Error::Pattern {
+                    pattern: budget.files.clone(),
+                    source,
+                })?
+                .compile_matcher();
+
+            for &encoding in &encodings {
+                if budget.encoding.is_some_and(|e| e != encoding) {
+                    continue;
+                }
+
+                let now = matching(&self.files, encoding, &glob);
+                let before = baseline.map(|b| matching(&b.files, encoding, &glob));
+
+                let files = now
+                    .iter()
+                    .map(|&(path, bytes)| {
+                        let baseline = before
+                            .as_ref()
+                            .and_then(|before| before.iter().find(|(p, _)| *p == path))
+                            .map(|&(_, bytes)| bytes);
+                        (path, bytes, baseline)
+                    })
+                    .collect();
+
+                outcomes.push(BudgetOutcome {
+                    budget,
+                    encoding,
+                    bytes: now.iter().map(|(_, bytes)| bytes).sum(),
+                    baseline_bytes: before.map(|b| b.iter().map(|(_, bytes)| bytes).sum()),
+                    files,
+                });
+            }
+        }
+
+        Ok(outcomes)
+    }
+}
+
+/// The files of one tree matching `glob`, with their [`served_size`].
+fn matching<'a>(
+    files: &'a [FileReport],
+    encoding: Encoding,
+    glob: &GlobMatcher,
+) -> Vec<(&'a str, u64)> {
+    files
+        .iter()
+        .filter(|f| f.encoding.is_none_or(|e| e == encoding) && glob.is_match(&f.path))
+        .filter_map(|f| Some((f.path.as_str(), served_size(f)?)))
+        .collect()
+}
@@ -0,0 +1,255 @@
+use std::{
+    io,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use futures::StreamExt as _;
+use serde::{Deserialize, Serialize};
+use walkdir::{DirEntry, WalkDir};
+
+use crate::{
+    atomic::write_atomic,
+    encoding::encode,
+    error::{relative, Error},
+    manifest::Source,
+    naming::HashedName,
+    BrotliSettings, Dirs, Encoding,
+};
+
+pub(crate) fn identity_files(dirs: &Dirs) -> impl Iterator<Item = Result<DirEntry, Error>> + '_ {
+    WalkDir::new(&dirs.identity)
+        .follow_links(true)
+        .into_iter()
+        .filter_map(|e| match e {
+            Ok(entry) => {
+                if entry.path().is_file() {
+                    Some(Ok(entry))
+                } else {
+                    None
+                }
+            }
+            Err(source) => Some(Err(Error::Walk {
+                path: source.path().unwrap_or(&dirs.identity).to_owned(),
+                source,
+            })),
+        })
+}
+
+pub(crate) struct YewCompressor {
+    g: GenerationSpec,
+}
+
+/// What a single [`YewCompressor`] did, logged once every job is done.
+#[derive(Debug, Clone)]
+pub struct CompressionStats {
+    pub file_name: String,
+    pub target: PathBuf,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub elapsed: Duration,
+    /// the output didn't meet [`MinSavings`], so nothing was written to `target`
+    pub skipped: bool,
+}
+
+/// How much smaller than the identity file a compressed file has to be to get written. When it's
+/// not, the server falls back to the identity file.
+///
+/// Only applies to `assets`, everything else is embedded into the server compressed only.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MinSavings {
+    pub bytes: u64,
+    /// saved bytes over the size of the identity file, from 0 to 1
+    pub ratio: f64,
+}
+
+impl Default for MinSavings {
+    /// anything that is smaller at all
+    fn default() -> Self {
+        Self {
+            bytes: 1,
+            ratio: 0.0,
+        }
+    }
+}
+
+impl MinSavings {
+    fn is_met(&self, bytes_in: u64, bytes_out: u64) -> bool {
+        let saved = bytes_in.saturating_sub(bytes_out);
+        saved >= self.bytes && saved as f64 >= self.ratio * bytes_in as f64
+    }
+}
+
+impl YewCompressor {
+    fn new(g: GenerationSpec) -> Self {
+        Self { g }
+    }
+
+    /// CPU-bound, run it on the blocking pool.
+    fn compress(&self) -> Result<CompressionStats, Error> {
+        let started = Instant::now();
+        let target = self.g.target.clone();
+
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent).map_err(|source| Error::CreateDir {
+                path: parent.to_owned(),
+                source,
+            })?;
+        }
+
+        let identity_path = self.g.identity_file.path();
+        let buf = std::fs::read(identity_path).map_err(|source| Error::Read {
+            path: identity_path.to_owned(),
+            source,
+        })?;
+
+        let write_error = |source| Error::Write {
+            path: target.clone(),
+            source,
+        };
+
+        let mut compressed = Vec::new();
+        encode(self.g.encoding, self.g.brotli, &mut compressed, &buf).map_err(write_error)?;
+
+        let bytes_out = compressed.len() as u64;
+        let skipped = self
+            .g
+            .min_savings
+            .is_some_and(|m| !m.is_met(buf.len() as u64, bytes_out));
+
+        if skipped {
+            // a variant left over from settings that paid off would be served instead
+            if let Err(source) = std::fs::remove_file(&target) {
+                if source.kind() != io::ErrorKind::NotFound {
+                    return Err(Error::Remove {
+                        path: target,
+                        source,
+                    });
+                }
+            }
+        } else {
+            write_atomic(&target, &compressed).map_err(write_error)?;
+        }
+
+        Ok(CompressionStats {
+            file_name: self
+                .g
+                .identity_file
+                .file_name()
+                .to_string_lossy()
+                .into_owned(),
+            bytes_in: buf.len() as u64,
+            bytes_out,
+            target,
+            elapsed: started.elapsed(),
+            skipped,
+        })
+    }
+}
+
+/// Fans the compressors out over the blocking pool, at most `jobs` at a time. The stats come
+/// back next to their spec in the order of `specs`, whichever job finishes first.
+pub(crate) async fn run_compressors(
+    specs: Vec<GenerationSpec>,
+    jobs: NonZeroUsize,
+) -> Vec<(GenerationSpec, Result<CompressionStats, Error>)> {
+    let mut stats: Vec<(usize, GenerationSpec, Result<CompressionStats, Error>)> =
+        futures::stream::iter(specs)
+            .enumerate()
+            .map(|(i, g)| {
+                let path = g.identity_file.path().to_owned();
+                let compressor = YewCompressor::new(g.clone());
+                async move {
+                    let stats = tokio::task::spawn_blocking(move || compressor.compress())
+                        .await
+                        .unwrap_or(Err(Error::Panicked { path }));
+                    (i, g, stats)
+                }
+            })
+            .buffer_unordered(jobs.get())
+            .collect()
+            .await;
+
+    stats.sort_by_key(|(i, _, _)| *i);
+    stats.into_iter().map(|(_, g, s)| (g, s)).collect()
+}
+
+/// An identity file to compress, along with what it looks like right now.
+#[derive(Clone)]
+pub(crate) struct Input {
+    pub(crate) entry: DirEntry,
+    /// the path relative to the identity directory, see [`manifest_path`](crate::manifest::manifest_path)
+    pub(crate) key: String,
+    pub(crate) source: Source,
+}
+
+#[derive(Clone)]
+pub(crate) struct GenerationSpec {
+    pub(crate) identity_file: DirEntry,
+    /// see [`Input::key`]
+    pub(crate) key: String,
+    pub(crate) source: Source,
+    pub(crate) encoding: Encoding,
+    /// only set for [`Encoding::Brotli`]
+    pub(crate) brotli: Option<BrotliSettings>,
+    /// only set for `assets`, see [`MinSavings`]
+    pub(crate) min_savings: Option<MinSavings>,
+    /// see [`GenerationSpec::to_target`]
+    pub(crate) target: PathBuf,
+}
+
+impl GenerationSpec {
+    pub(crate) fn new(
+        dirs: &Dirs,
+        input: &Input,
+        encoding: Encoding,
+        brotli: Option<BrotliSettings>,
+        min_savings: MinSavings,
+    ) -> Result<Self, Error> {
+        let in_assets = input.entry.path().starts_with(dirs.identity.join("assets"));
+        // trunk doesn't hash assets, so the name has to change along with the content
+        let hash = in_assets.then_some(input.source.hash.as_str());
+        let target = Self::to_target(dirs, &input.entry, hash, encoding)?;
+        Ok(Self {
+            identity_file: input.entry.clone(),
+            key: input.key.clone(),
+            source: input.source.clone(),
+            encoding,
+            brotli,
+            min_savings: in_assets.then_some(min_savings),
+            target,
+        })
+    }
+
+    /// e.g. `<output>/brotli/assets/logo-<hash>.svg.br`, see [`HashedName`]
+    fn to_target(
+        dirs: &Dirs,
+        identity_file: &DirEntry,
+        hash: Option<&str>,
+        encoding: Encoding,
+    ) -> Result<PathBuf, Error> {
+        let identity_file_path = identity_file.path();
+        let encoding_suffix = encoding.suffix();
+        let mid_diff = relative(identity_file_path, &dirs.identity)?
+            .parent()
+            .unwrap_or(Path::new(""));
+        let file_name = identity_file
+            .file_name()
+            .to_str()
+            .ok_or_else(|| Error::NonUtf8Path {
+                path: identity_file_path.to_owned(),
+            })?;
+
+        let name = match hash {
+            Some(hash) => HashedName::new(file_name, hash).format(),
+            None => file_name.to_owned(),
+        };
+
+        Ok(dirs
+            .tree(encoding)
+            .join(mid_diff)
+            .join(format!("{name}.{encoding_suffix}")))
+    }
+}
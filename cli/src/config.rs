@@ -0,0 +1,149 @@
+use std::{
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{error::Error, BrotliPreset, Budget, Encoding, HashAlgorithm};
+
+/// The file `trunk-compress` looks for in the working directory when `--config` is not given.
+pub const CONFIG_FILE: &str = "trunk-compress.toml";
+
+/// Trunk's own config file, read when there is no [`CONFIG_FILE`]. The settings go into a
+/// `[compress]` table, every other table belongs to trunk.
+pub const TRUNK_CONFIG_FILE: &str = "Trunk.toml";
+
+/// Settings read from `trunk-compress.toml`, or the `[compress]` table of `Trunk.toml`, applied
+/// with [`CompressorBuilder::config`](crate::CompressorBuilder::config). Everything is optional.
+///
+/// ```toml
+/// dist = "../frontend/dist"
+/// encodings = ["brotli", "gzip"]
+/// hash = "blake3"
+///
+/// [brotli]
+/// quality = 11
+/// presets = ["wasm=11:24"]
+///
+/// [min_savings]
+/// ratio = 0.1
+///
+/// [files]
+/// include = ["assets/*.json"]
+/// exclude = ["*.woff2", "*.avif", "*.webp", "*.zip"]
+///
+/// [[budgets]]
+/// files = "*_bg.wasm"
+/// max = "1.5 MB"
+/// ```
+///
+/// Relative directories are resolved against the directory of the config file.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// see [`CompressorBuilder::dist`](crate::CompressorBuilder::dist), [`DEFAULT_DIST`](crate::DEFAULT_DIST) if not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dist: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub identity_dir: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_dir: Option<PathBuf>,
+    /// only brotli if empty
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub encodings: Vec<Encoding>,
+    /// BLAKE3 if not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hash: Option<HashAlgorithm>,
+    /// the number of cpus if not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jobs: Option<NonZeroUsize>,
+    pub brotli: BrotliOptions,
+    pub min_savings: MinSavingsOptions,
+    pub files: FilesConfig,
+    /// checked by the cli after every run, see [`Report::check_budgets`](crate::Report::check_budgets)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub budgets: Vec<Budget>,
+}
+
+/// See [`CompressorBuilder::quality`](crate::CompressorBuilder::quality) and the setters after it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BrotliOptions {
+    /// 11 if not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quality: Option<u32>,
+    /// 22 if not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lgwin: Option<u32>,
+    /// `EXT=QUALITY[:LGWIN]`, like `--preset`
+    pub presets: Vec<BrotliPreset>,
+}
+
+/// See [`MinSavings`](crate::MinSavings), whose defaults apply to what is not set.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MinSavingsOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ratio: Option<f64>,
+}
+
+/// Which identity files get compressed, see [`CompressorBuilder::include`](crate::CompressorBuilder::include).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FilesConfig {
+    /// compressed even if excluded
+    pub include: Vec<String>,
+    /// skipped on top of the default list of media files and `index.html`
+    pub exclude: Vec<String>,
+}
+
+impl Config {
+    /// Reads the `[compress]` table if the file is a [`TRUNK_CONFIG_FILE`], the whole file
+    /// otherwise.
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        let text = std::fs::read_to_string(path).map_err(|source| Error::ReadConfig {
+            path: path.to_owned(),
+            source,
+        })?;
+        let config_error = |source| Error::Config {
+            path: path.to_owned(),
+            source,
+        };
+
+        let mut config: Self = if path.file_name() == Some(TRUNK_CONFIG_FILE.as_ref()) {
+            let mut trunk: toml::Table = toml::from_str(&text).map_err(config_error)?;
+            match trunk.remove("compress") {
+                Some(table) => table.try_into().map_err(config_error)?,
+                None => Self::default(),
+            }
+        } else {
+            toml::from_str(&text).map_err(config_error)?
+        };
+
+        let base = path.parent().unwrap_or(Path::new(""));
+        for dir in [
+            &mut config.dist,
+            &mut config.identity_dir,
+            &mut config.output_dir,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            *dir = base.join(&*dir);
+        }
+
+        Ok(config)
+    }
+
+    /// The config file in the working directory: [`CONFIG_FILE`], or [`TRUNK_CONFIG_FILE`] if
+    /// there is none.
+    pub fn find() -> Option<&'static Path> {
+        [CONFIG_FILE, TRUNK_CONFIG_FILE]
+            .into_iter()
+            .map(Path::new)
+            .find(|path| path.is_file())
+    }
+}
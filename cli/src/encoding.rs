@@ -0,0 +1,216 @@
+use std::{
+    fmt,
+    io::{self, Read, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// Brotli encoder parameters, see [`brotli::enc::BrotliEncoderParams`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BrotliSettings {
+    /// 0 (fastest) to 11 (smallest)
+    pub quality: u32,
+    /// log2 of the sliding window size, 10 to 24
+    pub lgwin: u32,
+}
+
+impl Default for BrotliSettings {
+    fn default() -> Self {
+        // the library defaults
+        Self {
+            quality: 11,
+            lgwin: 22,
+        }
+    }
+}
+
+/// Brotli settings for files with a given extension, e.g. `wasm=11:24`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BrotliPreset {
+    pub extension: String,
+    pub quality: Option<u32>,
+    pub lgwin: Option<u32>,
+}
+
+impl FromStr for BrotliPreset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (extension, settings) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected EXT=QUALITY[:LGWIN], got {s:?}"))?;
+
+        let (quality, lgwin) = match settings.split_once(':') {
+            Some((quality, lgwin)) => (quality, Some(lgwin)),
+            None => (settings, None),
+        };
+
+        let parse = |v: &str, range: std::ops::RangeInclusive<u32>, what: &str| {
+            if v.is_empty() {
+                return Ok(None);
+            }
+            match v.parse::<u32>() {
+                Ok(v) if range.contains(&v) => Ok(Some(v)),
+                _ => Err(format!(
+                    "{what} must be within {}..={}, got {v:?}",
+                    range.start(),
+                    range.end()
+                )),
+            }
+        };
+
+        Ok(Self {
+            extension: extension.trim_start_matches('.').to_owned(),
+            quality: parse(quality, 0..=11, "quality")?,
+            lgwin: lgwin.map_or(Ok(None), |l| parse(l, 10..=24, "lgwin"))?,
+        })
+    }
+}
+
+/// the inverse of [`BrotliPreset::from_str`]
+impl fmt::Display for BrotliPreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}=", self.extension)?;
+        if let Some(quality) = self.quality {
+            write!(f, "{quality}")?;
+        }
+        if let Some(lgwin) = self.lgwin {
+            write!(f, ":{lgwin}")?;
+        }
+        Ok(())
+    }
+}
+
+/// as a string, like on the command line
+impl Serialize for BrotliPreset {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for BrotliPreset {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// The brotli settings of a run: the defaults plus the per-extension presets.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct BrotliConfig {
+    pub(crate) default: BrotliSettings,
+    pub(crate) presets: Vec<BrotliPreset>,
+}
+
+impl BrotliConfig {
+    /// Later presets win over earlier ones.
+    pub(crate) fn for_file(&self, file_name: &str) -> BrotliSettings {
+        self.presets
+            .iter()
+            .filter(|p| file_name.ends_with(&format!(".{}", p.extension)))
+            .fold(self.default, |settings, p| BrotliSettings {
+                quality: p.quality.unwrap_or(settings.quality),
+                lgwin: p.lgwin.unwrap_or(settings.lgwin),
+            })
+    }
+}
+
+/// A compressed tree that can be generated next to `identity`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    #[cfg_attr(feature = "cli", value(name = "br", alias = "brotli"))]
+    #[serde(alias = "br")]
+    Brotli,
+    #[cfg_attr(feature = "cli", value(alias = "gz"))]
+    #[serde(alias = "gz")]
+    Gzip,
+    #[cfg_attr(feature = "cli", value(alias = "zst"))]
+    #[serde(alias = "zst")]
+    Zstd,
+}
+
+impl Encoding {
+    pub(crate) const ALL: [Encoding; 3] = [Encoding::Brotli, Encoding::Gzip, Encoding::Zstd];
+
+    /// name of the tree under the output directory
+    pub(crate) fn dir_name(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "brotli",
+            Encoding::Gzip => "gzip",
+            Encoding::Zstd => "zstd",
+        }
+    }
+
+    /// file extension appended to every compressed file, without the dot
+    pub(crate) fn suffix(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gz",
+            Encoding::Zstd => "zst",
+        }
+    }
+}
+
+/// the name of the tree, e.g. `brotli`
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.dir_name())
+    }
+}
+
+pub(crate) fn encode<W: Write>(
+    encoding: Encoding,
+    brotli: Option<BrotliSettings>,
+    output: W,
+    data: &[u8],
+) -> io::Result<()> {
+    match encoding {
+        Encoding::Brotli => {
+            let settings = brotli.unwrap_or_default();
+            let params = brotli::enc::BrotliEncoderParams {
+                quality: settings.quality as i32,
+                lgwin: settings.lgwin as i32,
+                ..Default::default()
+            };
+            let mut encoder = brotli::CompressorWriter::with_params(output, 4096, &params);
+            encoder.write_all(data)?;
+            encoder.into_inner().flush()
+        }
+        Encoding::Gzip => {
+            let mut encoder = flate2::write::GzEncoder::new(output, flate2::Compression::default());
+            encoder.write_all(data)?;
+            encoder.finish()?.flush()
+        }
+        Encoding::Zstd => {
+            let mut encoder = zstd::Encoder::new(output, zstd::DEFAULT_COMPRESSION_LEVEL)?;
+            encoder.write_all(data)?;
+            encoder.finish()?.flush()
+        }
+    }
+}
+
+/// Reads what `input` decodes to, failing on corrupt or truncated data.
+pub(crate) fn decoder<'a, R: Read + 'a>(
+    encoding: Encoding,
+    input: R,
+) -> io::Result<Box<dyn Read + 'a>> {
+    Ok(match encoding {
+        Encoding::Brotli => Box::new(brotli::Decompressor::new(input, 4096)),
+        Encoding::Gzip => Box::new(flate2::read::GzDecoder::new(input)),
+        Encoding::Zstd => Box::new(zstd::Decoder::new(input)?),
+    })
+}
+
+/// Whether the compressed file at `path` decodes to `size` bytes, which catches outputs that
+/// were cut short.
+pub(crate) fn decodes_to(encoding: Encoding, path: &Path, size: u64) -> bool {
+    let decoded = std::fs::File::open(path)
+        .and_then(|f| decoder(encoding, io::BufReader::new(f)))
+        .and_then(|mut d| io::copy(&mut d, &mut io::sink()));
+    matches!(decoded, Ok(len) if len == size)
+}
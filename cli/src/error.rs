@@ -0,0 +1,56 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+/// Everything that can go wrong while compressing. Most of these are reported per file, see
+/// [`Report`](crate::Report); only the ones returned from [`Compressor::run`](crate::Compressor::run)
+/// itself stop the run.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("failed to read {path:?}: {source}")]
+    Read { path: PathBuf, source: io::Error },
+    #[error("failed to write {path:?}: {source}")]
+    Write { path: PathBuf, source: io::Error },
+    #[error("failed to create directory {path:?}: {source}")]
+    CreateDir { path: PathBuf, source: io::Error },
+    #[error("failed to remove {path:?}: {source}")]
+    Remove { path: PathBuf, source: io::Error },
+    #[error("failed to walk {path:?}: {source}")]
+    Walk {
+        path: PathBuf,
+        source: walkdir::Error,
+    },
+    #[error("{path:?} is not valid UTF-8")]
+    NonUtf8Path { path: PathBuf },
+    #[error("{path:?} is not inside {base:?}")]
+    OutsideDir { path: PathBuf, base: PathBuf },
+    #[error("compressing {path:?} panicked")]
+    Panicked { path: PathBuf },
+    #[error("invalid pattern {pattern:?}: {source}")]
+    Pattern {
+        pattern: String,
+        source: globset::Error,
+    },
+    #[error("failed to read config {path:?}: {source}")]
+    ReadConfig { path: PathBuf, source: io::Error },
+    #[error("invalid config {path:?}: {source}")]
+    Config {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("invalid baseline report {path:?}: {source}")]
+    Baseline {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[error("failed to start the tokio runtime: {0}")]
+    Runtime(io::Error),
+}
+
+pub(crate) fn relative<'a>(path: &'a Path, base: &Path) -> Result<&'a Path, Error> {
+    path.strip_prefix(base).map_err(|_| Error::OutsideDir {
+        path: path.to_owned(),
+        base: base.to_owned(),
+    })
+}
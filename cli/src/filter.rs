@@ -0,0 +1,81 @@
+use globset::{Glob, GlobSet, GlobSetBuilder};
+
+use crate::{error::Error, FileStatus};
+
+/// Skipped unless included again: formats that are compressed already, `index.html`, and the
+/// directory trunk stages a build in before moving it into place.
+///
+/// `index.html` is only the template the server renders pages into, embedded uncompressed by
+/// `serve_yew::index!`. A compressed variant would never be served: rendered pages are
+/// compressed as they are sent, and cached compressed with `ServeYew::cache_renders`.
+pub const DEFAULT_EXCLUDES: &[&str] = &[
+    "*.png",
+    "*.jpg",
+    "*.jpeg",
+    "*.gif",
+    "*.ico",
+    "*.mp3",
+    "*.mp4",
+    "*.webm",
+    "*.ogg",
+    "*.wav",
+    "**/index.html",
+    ".stage/**",
+];
+
+/// Decides which identity files get compressed.
+///
+/// Patterns are globs matched against the path relative to the identity directory, with `/` as the
+/// separator, e.g. `assets/*.json`. `*` also matches across directories, so `*.woff2` skips fonts
+/// anywhere in the tree. A file is compressed when it is not excluded, or when it is included
+/// explicitly; includes win over excludes, the default ones as well as the configured ones.
+#[derive(Debug, Clone)]
+pub(crate) struct FileFilter {
+    include: GlobSet,
+    default_exclude: GlobSet,
+    exclude: GlobSet,
+}
+
+impl FileFilter {
+    pub(crate) fn new(include: &[String], exclude: &[String]) -> Result<Self, Error> {
+        Ok(Self {
+            include: glob_set(include.iter().map(String::as_str))?,
+            default_exclude: glob_set(DEFAULT_EXCLUDES.iter().copied())?,
+            exclude: glob_set(exclude.iter().map(String::as_str))?,
+        })
+    }
+
+    /// `path` is relative to the identity directory, as in the manifest
+    pub(crate) fn should_compress(&self, path: &str) -> bool {
+        self.exclusion(path).is_none()
+    }
+
+    /// Why `path` isn't compressed, [`FileStatus::Excluded`] if a configured pattern and one of
+    /// the [`DEFAULT_EXCLUDES`] both match.
+    pub(crate) fn exclusion(&self, path: &str) -> Option<FileStatus> {
+        if self.include.is_match(path) {
+            None
+        } else if self.exclude.is_match(path) {
+            Some(FileStatus::Excluded)
+        } else if self.default_exclude.is_match(path) {
+            Some(FileStatus::ExcludedByDefault)
+        } else {
+            None
+        }
+    }
+}
+
+fn glob_set<'a>(patterns: impl IntoIterator<Item = &'a str>) -> Result<GlobSet, Error> {
+    let patterns: Vec<&str> = patterns.into_iter().collect();
+    let mut builder = GlobSetBuilder::new();
+    for pattern in &patterns {
+        builder.add(Glob::new(pattern).map_err(|source| Error::Pattern {
+            pattern: pattern.to_string(),
+            source,
+        })?);
+    }
+    builder.build().map_err(|source| Error::Pattern {
+        pattern: patterns.join(", "),
+        source,
+    })
+}
@@ -0,0 +1,30 @@
+use serde::{Deserialize, Serialize};
+use sha2::Digest as _;
+
+/// How identity files are fingerprinted. The hash decides whether a file changed, and is part of
+/// the names of compressed assets so browsers can cache them forever.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    #[default]
+    Blake3,
+    Sha256,
+}
+
+impl HashAlgorithm {
+    /// lowercase hex
+    pub(crate) fn hash(self, data: &[u8]) -> String {
+        match self {
+            HashAlgorithm::Blake3 => blake3::hash(data).to_hex().to_string(),
+            HashAlgorithm::Sha256 => hex(&sha2::Sha256::digest(data)),
+        }
+    }
+}
+
+/// How much of the hash goes into a file name, like the 16 hex digits trunk uses.
+pub(crate) const NAME_HASH_LEN: usize = 16;
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
@@ -0,0 +1,881 @@
+//! Pre-compresses the files trunk builds, so a server can embed and serve them as is.
+//!
+//! The `trunk-compress` binary is a thin wrapper around [`Compressor`], which can also be used
+//! directly from a `build.rs`:
+//!
+//! ```no_run
+//! let report = trunk_compress::Compressor::builder()
+//!     .dist("../frontend/dist")
+//!     .build()
+//!     .expect("invalid settings")
+//!     .run_blocking()
+//!     .expect("failed to compress the frontend");
+//!
+//! report.emit_rerun_if_changed();
+//! assert!(report.is_success(), "{:?}", report.errors);
+//! ```
+
+use std::{
+    collections::HashSet,
+    io,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    time::{Instant, UNIX_EPOCH},
+};
+
+use tracing::info;
+use walkdir::{DirEntry, WalkDir};
+
+mod atomic;
+mod budget;
+mod compressor;
+mod config;
+mod encoding;
+mod error;
+mod filter;
+mod hash;
+mod manifest;
+mod naming;
+mod summary;
+mod verify;
+
+pub use budget::{Baseline, Budget, BudgetOutcome, ByteSize};
+pub use compressor::{CompressionStats, MinSavings};
+pub use config::{
+    BrotliOptions, Config, FilesConfig, MinSavingsOptions, CONFIG_FILE, TRUNK_CONFIG_FILE,
+};
+pub use encoding::{BrotliPreset, BrotliSettings, Encoding};
+pub use error::Error;
+pub use filter::DEFAULT_EXCLUDES;
+pub use hash::HashAlgorithm;
+pub use summary::{FileReport, FileStatus, Summary, Totals};
+pub use verify::{Problem, Verification};
+
+use compressor::{identity_files, run_compressors, GenerationSpec, Input};
+use encoding::{decodes_to, BrotliConfig};
+use error::relative;
+use filter::FileFilter;
+use manifest::{manifest_path, Manifest, Source, MANIFEST};
+use naming::HashedName;
+
+/// Where trunk's dist directory is expected when nothing else is configured, relative to the
+/// backend crate.
+pub const DEFAULT_DIST: &str = "../frontend/dist/";
+
+/// What a run did, and everything that went wrong along the way.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub compressed: Vec<CompressionStats>,
+    /// compressed, but not written because it didn't meet the [`MinSavings`]
+    pub skipped: Vec<CompressionStats>,
+    /// outdated compressed files and directories that were deleted
+    pub removed: Vec<PathBuf>,
+    /// compressed files that only needed a new name, from the old path to the new one
+    pub renamed: Vec<(PathBuf, PathBuf)>,
+    /// only filled in a dry run: the files that would have been compressed, some of which may
+    /// have turned out not to pay off. `removed` and `renamed` hold what would have happened.
+    pub pending: Vec<PathBuf>,
+    pub errors: Vec<Error>,
+    /// the directory the inputs were read from
+    pub identity_dir: PathBuf,
+    /// every file found in the identity directory, compressed or not
+    pub inputs: Vec<PathBuf>,
+    /// every input once per tree, or once if it's excluded, sorted by path
+    pub files: Vec<FileReport>,
+}
+
+impl Report {
+    pub fn is_success(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// Whether any compressed tree looks different than before the run, or would in a dry run.
+    pub fn changed(&self) -> bool {
+        !self.pending.is_empty()
+            || !self.compressed.is_empty()
+            || !self.skipped.is_empty()
+            || !self.removed.is_empty()
+            || !self.renamed.is_empty()
+    }
+
+    /// The files along with the totals per tree, see [`Summary`].
+    pub fn summary(&self) -> Summary<'_> {
+        Summary::new(&self.files)
+    }
+
+    /// Prints a `cargo:rerun-if-changed` line for the identity directory and every file in it,
+    /// for use in a `build.rs`.
+    pub fn emit_rerun_if_changed(&self) {
+        println!("cargo:rerun-if-changed={}", self.identity_dir.display());
+        for input in &self.inputs {
+            println!("cargo:rerun-if-changed={}", input.display());
+        }
+    }
+}
+
+/// Where the trees live on disk.
+#[derive(Debug, Clone)]
+pub(crate) struct Dirs {
+    /// trunk's output, e.g. `frontend/dist/identity`
+    pub(crate) identity: PathBuf,
+    /// parent of the compressed trees, e.g. `frontend/dist`
+    pub(crate) output: PathBuf,
+}
+
+impl Dirs {
+    pub(crate) fn tree(&self, encoding: Encoding) -> PathBuf {
+        self.output.join(encoding.dir_name())
+    }
+}
+
+/// Brings the compressed trees next to trunk's output up to date, see [`Compressor::builder`].
+#[derive(Debug, Clone)]
+pub struct Compressor {
+    dirs: Dirs,
+    encodings: Vec<Encoding>,
+    brotli: BrotliConfig,
+    min_savings: MinSavings,
+    filter: FileFilter,
+    hash: HashAlgorithm,
+    jobs: NonZeroUsize,
+    dry_run: bool,
+}
+
+impl Compressor {
+    pub fn builder() -> CompressorBuilder {
+        CompressorBuilder::default()
+    }
+
+    pub fn identity_dir(&self) -> &Path {
+        &self.dirs.identity
+    }
+
+    /// Problems with single files end up in the [`Report`], the returned error is for when
+    /// nothing can be done at all.
+    pub async fn run(&self) -> Result<Report, Error> {
+        compress(self).await
+    }
+
+    /// Decodes every compressed file and compares it with its identity file, without changing
+    /// anything.
+    pub async fn verify(&self) -> Result<Verification, Error> {
+        verify::verify(self).await
+    }
+
+    /// [`Compressor::run`] on a runtime of its own, for callers without one like `build.rs`.
+    pub fn run_blocking(&self) -> Result<Report, Error> {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .map_err(Error::Runtime)?
+            .block_on(self.run())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CompressorBuilder {
+    dist: PathBuf,
+    identity_dir: Option<PathBuf>,
+    output_dir: Option<PathBuf>,
+    encodings: Vec<Encoding>,
+    brotli: BrotliConfig,
+    min_savings: MinSavings,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    hash: HashAlgorithm,
+    jobs: Option<NonZeroUsize>,
+    dry_run: bool,
+}
+
+impl Default for CompressorBuilder {
+    fn default() -> Self {
+        Self {
+            dist: PathBuf::from(DEFAULT_DIST),
+            identity_dir: None,
+            output_dir: None,
+            encodings: Vec::new(),
+            brotli: BrotliConfig::default(),
+            min_savings: MinSavings::default(),
+            include: Vec::new(),
+            exclude: Vec::new(),
+            hash: HashAlgorithm::default(),
+            jobs: None,
+            dry_run: false,
+        }
+    }
+}
+
+impl CompressorBuilder {
+    /// trunk's dist directory, the parent of the `identity` and compressed trees
+    pub fn dist(mut self, dist: impl Into<PathBuf>) -> Self {
+        self.dist = dist.into();
+        self
+    }
+
+    /// the uncompressed files written by trunk, `<dist>/identity` by default
+    pub fn identity_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.identity_dir = Some(dir.into());
+        self
+    }
+
+    /// where the compressed trees are written, `<dist>` by default
+    pub fn output_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.output_dir = Some(dir.into());
+        self
+    }
+
+    /// adds a compressed tree to generate, only brotli if none is added
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        if !self.encodings.contains(&encoding) {
+            self.encodings.push(encoding);
+        }
+        self
+    }
+
+    pub fn encodings(self, encodings: impl IntoIterator<Item = Encoding>) -> Self {
+        encodings.into_iter().fold(self, Self::encoding)
+    }
+
+    /// brotli quality, from 0 (fastest) to 11 (smallest), larger values are capped
+    pub fn quality(mut self, quality: u32) -> Self {
+        self.brotli.default.quality = quality.min(11);
+        self
+    }
+
+    /// log2 of the brotli window size, capped to 10..=24
+    pub fn lgwin(mut self, lgwin: u32) -> Self {
+        self.brotli.default.lgwin = lgwin.clamp(10, 24);
+        self
+    }
+
+    /// brotli settings for one file extension, later presets win over earlier ones
+    pub fn preset(mut self, preset: BrotliPreset) -> Self {
+        self.brotli.presets.push(preset);
+        self
+    }
+
+    /// how many bytes an asset's compressed variant has to save to get written, 1 by default
+    pub fn min_savings_bytes(mut self, bytes: u64) -> Self {
+        self.min_savings.bytes = bytes;
+        self
+    }
+
+    /// which fraction of an asset's size its compressed variant has to save to get written,
+    /// capped to 0..=1, 0 by default
+    pub fn min_savings_ratio(mut self, ratio: f64) -> Self {
+        self.min_savings.ratio = ratio.clamp(0.0, 1.0);
+        self
+    }
+
+    /// compresses the files matching a glob even if they are excluded, e.g. a large `assets/*.json`
+    ///
+    /// Patterns are matched against the path relative to the identity directory.
+    pub fn include(mut self, pattern: impl Into<String>) -> Self {
+        self.include.push(pattern.into());
+        self
+    }
+
+    /// skips the files matching a glob, on top of [`DEFAULT_EXCLUDES`], e.g. `*.woff2`
+    pub fn exclude(mut self, pattern: impl Into<String>) -> Self {
+        self.exclude.push(pattern.into());
+        self
+    }
+
+    /// applies a config file: the settings it has replace the ones set before, the ones it
+    /// leaves out are kept, its presets and patterns are added to them
+    pub fn config(mut self, config: &Config) -> Self {
+        if let Some(dist) = &config.dist {
+            self.dist = dist.clone();
+        }
+        if let Some(dir) = &config.identity_dir {
+            self.identity_dir = Some(dir.clone());
+        }
+        if let Some(dir) = &config.output_dir {
+            self.output_dir = Some(dir.clone());
+        }
+        if !config.encodings.is_empty() {
+            self.encodings.clear();
+        }
+        if let Some(jobs) = config.jobs {
+            self.jobs = Some(jobs);
+        }
+        self.include.extend(config.files.include.iter().cloned());
+        self.exclude.extend(config.files.exclude.iter().cloned());
+        self.brotli
+            .presets
+            .extend(config.brotli.presets.iter().cloned());
+
+        if let Some(quality) = config.brotli.quality {
+            self = self.quality(quality);
+        }
+        if let Some(lgwin) = config.brotli.lgwin {
+            self = self.lgwin(lgwin);
+        }
+        if let Some(bytes) = config.min_savings.bytes {
+            self = self.min_savings_bytes(bytes);
+        }
+        if let Some(ratio) = config.min_savings.ratio {
+            self = self.min_savings_ratio(ratio);
+        }
+        if let Some(hash) = config.hash {
+            self = self.hash(hash);
+        }
+
+        self.encodings(config.encodings.iter().copied())
+    }
+
+    /// how identity files are fingerprinted, BLAKE3 by default
+    pub fn hash(mut self, hash: HashAlgorithm) -> Self {
+        self.hash = hash;
+        self
+    }
+
+    /// how many files are compressed at once, the number of cpus by default
+    pub fn jobs(mut self, jobs: NonZeroUsize) -> Self {
+        self.jobs = Some(jobs);
+        self
+    }
+
+    /// plans the run without touching the disk, see [`Report::pending`]
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// fails if one of the include or exclude patterns is not a valid glob
+    pub fn build(self) -> Result<Compressor, Error> {
+        let mut encodings = self.encodings;
+        if encodings.is_empty() {
+            encodings.push(Encoding::Brotli);
+        }
+        encodings.sort();
+
+        Ok(Compressor {
+            filter: FileFilter::new(&self.include, &self.exclude)?,
+            dirs: Dirs {
+                identity: self
+                    .identity_dir
+                    .unwrap_or_else(|| self.dist.join("identity")),
+                output: self.output_dir.unwrap_or(self.dist),
+            },
+            encodings,
+            brotli: self.brotli,
+            min_savings: self.min_savings,
+            hash: self.hash,
+            dry_run: self.dry_run,
+            jobs: self.jobs.unwrap_or_else(|| {
+                std::thread::available_parallelism().unwrap_or(NonZeroUsize::MIN)
+            }),
+        })
+    }
+}
+
+/// Brings every tree up to date. Problems with single files end up in the [`Report`], the
+/// returned error is for when nothing can be done at all.
+async fn compress(settings: &Compressor) -> Result<Report, Error> {
+    let dirs = &settings.dirs;
+    let mut report = Report::default();
+
+    let identity_dir = &dirs.identity;
+    let identity_exists = identity_dir.exists();
+    if !identity_exists && !settings.dry_run {
+        info!("identity directory does not exist, creating");
+        tokio::fs::create_dir_all(identity_dir)
+            .await
+            .map_err(|source| Error::CreateDir {
+                path: identity_dir.clone(),
+                source,
+            })?;
+    }
+
+    let identity_files: Vec<DirEntry> = if identity_exists || !settings.dry_run {
+        identity_files(dirs)
+            .filter_map(|e| e.map_err(|e| report.errors.push(e)).ok())
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    report.identity_dir = identity_dir.clone();
+    report.inputs = identity_files.iter().map(|e| e.path().to_owned()).collect();
+
+    let mut previous = Vec::new();
+    for &encoding in &settings.encodings {
+        previous.push((encoding, Manifest::read(&dirs.tree(encoding)).await));
+    }
+
+    let candidates: Vec<Input> = identity_files
+        .into_iter()
+        .filter_map(|e| {
+            if e.file_name().to_str().is_none() {
+                report.errors.push(Error::NonUtf8Path {
+                    path: e.path().to_owned(),
+                });
+                return None;
+            }
+
+            let key = match relative(e.path(), identity_dir) {
+                Ok(path) => manifest_path(path),
+                Err(err) => {
+                    report.errors.push(err);
+                    return None;
+                }
+            };
+            if let Some(status) = settings.filter.exclusion(&key) {
+                let size = e.metadata().map(|m| m.len()).unwrap_or_default();
+                report
+                    .files
+                    .push(FileReport::new(&key, None, status, size, None));
+                return None;
+            }
+
+            match fingerprint(&e, &key, settings.hash, &previous) {
+                Ok(source) => Some(Input {
+                    entry: e,
+                    key,
+                    source,
+                }),
+                Err(err) => {
+                    report.errors.push(err);
+                    None
+                }
+            }
+        })
+        .collect();
+
+    let mut to_be_generated = Vec::new();
+    let mut manifests = Vec::new();
+
+    for (encoding, previous) in previous {
+        let (specs, manifest) =
+            prepare_tree(settings, encoding, &candidates, previous, &mut report).await?;
+        to_be_generated.extend(specs);
+        manifests.push((encoding, manifest));
+    }
+
+    if settings.dry_run {
+        for g in to_be_generated {
+            info!("would compress {:?}", g.identity_file.path());
+            report.files.push(FileReport::new(
+                &g.key,
+                Some(g.encoding),
+                FileStatus::Pending,
+                g.source.size,
+                None,
+            ));
+            report.pending.push(g.target);
+        }
+        sort_files(&mut report);
+        return Ok(report);
+    }
+
+    // compress the to_be_generated files
+
+    for g in &to_be_generated {
+        match g.brotli {
+            Some(b) => info!(
+                "outputing target {:?} (quality {}, lgwin {})",
+                g.target, b.quality, b.lgwin
+            ),
+            None => info!("outputing target {:?}", g.target),
+        }
+    }
+
+    let started = Instant::now();
+    for (g, result) in run_compressors(to_be_generated, settings.jobs).await {
+        match result {
+            Ok(s) if s.skipped => {
+                info!(
+                    "not writing {:?}, {} -> {} bytes doesn't pay off",
+                    s.target, s.bytes_in, s.bytes_out
+                );
+                if let Some((_, manifest)) = manifests.iter_mut().find(|(e, _)| *e == g.encoding) {
+                    manifest.skip(&g, Some(s.bytes_out));
+                }
+                report
+                    .files
+                    .push(file_report(&g, FileStatus::BelowThreshold, &s));
+                report.skipped.push(s);
+            }
+            Ok(s) => {
+                report
+                    .files
+                    .push(file_report(&g, FileStatus::Compressed, &s));
+                report.compressed.push(s);
+            }
+            Err(e) => report.errors.push(e),
+        }
+    }
+    let stats = &report.compressed;
+
+    for s in stats {
+        info!(
+            "Done compressing {} into {:?}: {} -> {} bytes in {:.2?}",
+            s.file_name, s.target, s.bytes_in, s.bytes_out, s.elapsed
+        );
+    }
+
+    if !stats.is_empty() {
+        let bytes_in: u64 = stats.iter().map(|s| s.bytes_in).sum();
+        let bytes_out: u64 = stats.iter().map(|s| s.bytes_out).sum();
+        info!(
+            "compressed {} files: {} -> {} bytes ({:.1}%) in {:.2?}",
+            stats.len(),
+            bytes_in,
+            bytes_out,
+            bytes_out as f64 * 100.0 / bytes_in.max(1) as f64,
+            started.elapsed()
+        );
+    }
+
+    for (encoding, manifest) in manifests {
+        if let Err(e) = manifest.write(&dirs.tree(encoding)).await {
+            report.errors.push(e);
+        }
+    }
+
+    sort_files(&mut report);
+    Ok(report)
+}
+
+fn file_report(g: &GenerationSpec, status: FileStatus, s: &CompressionStats) -> FileReport {
+    FileReport::new(
+        &g.key,
+        Some(g.encoding),
+        status,
+        s.bytes_in,
+        Some(s.bytes_out),
+    )
+    .elapsed(s.elapsed)
+}
+
+fn sort_files(report: &mut Report) {
+    report
+        .files
+        .sort_by(|a, b| (&a.path, a.encoding).cmp(&(&b.path, b.encoding)));
+}
+
+/// Size and modification time of an identity file, and its hash: from the manifest of a
+/// previous run if the former two didn't change, otherwise by reading the file.
+fn fingerprint(
+    entry: &DirEntry,
+    key: &str,
+    hash: HashAlgorithm,
+    previous: &[(Encoding, Manifest)],
+) -> Result<Source, Error> {
+    let read_error = |source| Error::Read {
+        path: entry.path().to_owned(),
+        source,
+    };
+
+    let metadata = entry.metadata().map_err(|source| Error::Walk {
+        path: entry.path().to_owned(),
+        source,
+    })?;
+    let size = metadata.len();
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .and_then(|d| u64::try_from(d.as_nanos()).ok());
+
+    let cached = previous
+        .iter()
+        .find_map(|(_, m)| m.cached_hash(hash, key, size, modified));
+
+    let hash = match cached {
+        Some(cached) => cached.to_owned(),
+        None => hash.hash(&std::fs::read(entry.path()).map_err(read_error)?),
+    };
+
+    Ok(Source {
+        size,
+        modified,
+        hash,
+    })
+}
+
+/// Removes what's outdated in the tree of one encoding, and returns what still has to be
+/// compressed along with the manifest the tree will have once that's done.
+async fn prepare_tree(
+    settings: &Compressor,
+    encoding: Encoding,
+    candidates: &[Input],
+    previous: Manifest,
+    report: &mut Report,
+) -> Result<(Vec<GenerationSpec>, Manifest), Error> {
+    let dirs = &settings.dirs;
+    let identity_dir = &dirs.identity;
+    let compression_dir = dirs.tree(encoding);
+    let (removing, renaming, recompressing) = if settings.dry_run {
+        ("would remove", "would rename", "would recompress")
+    } else {
+        ("removing", "renaming", "recompressing")
+    };
+    if !compression_dir.exists() && !settings.dry_run {
+        info!("{} directory does not exist, creating", encoding.dir_name());
+        tokio::fs::create_dir_all(&compression_dir)
+            .await
+            .map_err(|source| Error::CreateDir {
+                path: compression_dir.clone(),
+                source,
+            })?;
+    }
+
+    let mut manifest = Manifest::new(settings.hash);
+    let mut to_be_generated = Vec::new();
+
+    for input in candidates {
+        let brotli = (encoding == Encoding::Brotli).then(|| {
+            settings
+                .brotli
+                .for_file(&input.entry.file_name().to_string_lossy())
+        });
+        let g = match GenerationSpec::new(dirs, input, encoding, brotli, settings.min_savings) {
+            Ok(g) => g,
+            Err(e) => {
+                report.errors.push(e);
+                continue;
+            }
+        };
+
+        let report_file = |status, output: &Path| {
+            let compressed_bytes = std::fs::metadata(output).map(|m| m.len()).ok();
+            FileReport::new(
+                &g.key,
+                Some(encoding),
+                status,
+                g.source.size,
+                compressed_bytes,
+            )
+        };
+
+        if let Some(skipped) = previous.was_skipped(&g) {
+            report.files.push(FileReport::new(
+                &g.key,
+                Some(encoding),
+                FileStatus::BelowThreshold,
+                g.source.size,
+                skipped.compressed_size,
+            ));
+            manifest.skip(&g, skipped.compressed_size);
+            continue;
+        }
+
+        manifest.insert(dirs, &g)?;
+
+        let written = previous.was_written(dirs, &g)? && g.target.is_file();
+        if written && decodes_to(encoding, &g.target, g.source.size) {
+            report
+                .files
+                .push(report_file(FileStatus::Unchanged, &g.target));
+            continue;
+        }
+
+        if let Some(old) = previous.renamed_output(dirs, &g)? {
+            let old = compression_dir.join(old);
+            let intact = old.is_file() && decodes_to(encoding, &old, g.source.size);
+            if intact && settings.dry_run {
+                info!("{renaming} {:?} to {:?}", old, g.target);
+                report.files.push(report_file(FileStatus::Renamed, &old));
+                report.renamed.push((old, g.target.clone()));
+                continue;
+            }
+            if intact {
+                info!("{renaming} {:?} to {:?}", old, g.target);
+                match std::fs::rename(&old, &g.target) {
+                    Ok(()) => {
+                        report
+                            .files
+                            .push(report_file(FileStatus::Renamed, &g.target));
+                        report.renamed.push((old, g.target.clone()));
+                        continue;
+                    }
+                    Err(source) => report.errors.push(Error::Write {
+                        path: g.target.clone(),
+                        source,
+                    }),
+                }
+            }
+        }
+
+        match previous.files.get(&g.key) {
+            _ if written => info!(
+                "{recompressing} {:?} because it doesn't decode to {}",
+                g.target, g.key
+            ),
+            Some(entry) if entry.source.hash == g.source.hash && g.target.is_file() => info!(
+                "{recompressing} {:?} because the compression settings changed",
+                g.target
+            ),
+            Some(_) if previous.hash != settings.hash => info!(
+                "{recompressing} {:?} because the hash algorithm changed",
+                g.target
+            ),
+            Some(_) => info!("{recompressing} {:?} because {} changed", g.target, g.key),
+            None => {}
+        }
+        to_be_generated.push(g);
+    }
+
+    // everything the manifest doesn't point to is left over from previous runs
+    let outputs: HashSet<PathBuf> = manifest
+        .files
+        .values()
+        .map(|e| compression_dir.join(&e.output))
+        .collect();
+
+    let mut deleting_dirs: Vec<PathBuf> = Vec::new();
+    let mut deleting_files: Vec<PathBuf> = Vec::new();
+
+    // in a dry run, there may be nothing to clean up yet
+    let old_entries = compression_dir
+        .exists()
+        .then(|| WalkDir::new(&compression_dir))
+        .into_iter()
+        .flatten();
+
+    for old_entry in old_entries {
+        let old_entry = match old_entry {
+            Ok(entry) => entry,
+            Err(source) => {
+                report.errors.push(Error::Walk {
+                    path: source.path().unwrap_or(&compression_dir).to_owned(),
+                    source,
+                });
+                continue;
+            }
+        };
+
+        if old_entry.path().is_dir() {
+            let corresponding_identity =
+                identity_dir.join(relative(old_entry.path(), &compression_dir)?);
+
+            if !corresponding_identity.exists() || !corresponding_identity.is_dir() {
+                info!("{removing} outdated directory {:?}", old_entry.path());
+                deleting_dirs.push(old_entry.path().to_owned());
+            }
+
+            continue;
+        }
+
+        if old_entry.path() == compression_dir.join(MANIFEST) {
+            continue;
+        }
+
+        if !outputs.contains(old_entry.path()) {
+            let old_scheme = old_entry.path().starts_with(compression_dir.join("assets"))
+                && old_entry
+                    .file_name()
+                    .to_str()
+                    .and_then(|f| f.strip_suffix(encoding.suffix()))
+                    .and_then(|f| f.strip_suffix('.'))
+                    .is_some_and(|f| HashedName::parse(f).is_none());
+            if old_scheme {
+                info!(
+                    "{removing} {:?}, named by an older version of trunk-compress",
+                    old_entry.path()
+                );
+            } else {
+                info!("{removing} outdated file {:?}", old_entry.path());
+            }
+            deleting_files.push(old_entry.path().to_owned());
+        }
+    }
+
+    if settings.dry_run {
+        report.removed.extend(deleting_dirs);
+        report.removed.extend(deleting_files);
+        return Ok((to_be_generated, manifest));
+    }
+
+    for dir in deleting_dirs {
+        match std::fs::remove_dir_all(&dir) {
+            Ok(()) => report.removed.push(dir),
+            Err(source) => report.errors.push(Error::Remove { path: dir, source }),
+        }
+    }
+    for file in deleting_files {
+        match std::fs::remove_file(&file) {
+            Ok(()) => report.removed.push(file),
+            // already gone along with its outdated directory
+            Err(source) if source.kind() == io::ErrorKind::NotFound => {}
+            Err(source) => report.errors.push(Error::Remove { path: file, source }),
+        }
+    }
+
+    Ok((to_be_generated, manifest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(report: &Report, path: &str) -> FileStatus {
+        let file = report.files.iter().find(|f| f.path == path);
+        file.unwrap_or_else(|| panic!("{path} is not in {:?}", report.files))
+            .status
+    }
+
+    #[test]
+    fn config_keeps_what_it_leaves_out() {
+        let config: Config = toml::from_str("[brotli]\nlgwin = 18").unwrap();
+        let compressor = Compressor::builder()
+            .quality(5)
+            .hash(HashAlgorithm::Sha256)
+            .min_savings_ratio(0.5)
+            .config(&config)
+            .build()
+            .unwrap();
+
+        let BrotliSettings { quality, lgwin } = compressor.brotli.default;
+        assert_eq!((quality, lgwin), (5, 18));
+        assert_eq!(compressor.hash, HashAlgorithm::Sha256);
+        assert_eq!(compressor.min_savings.ratio, 0.5);
+    }
+
+    #[tokio::test]
+    async fn raising_the_threshold_removes_written_variants() {
+        let dist = tempfile::tempdir().unwrap();
+        let assets = dist.path().join("identity/assets");
+        std::fs::create_dir_all(&assets).unwrap();
+        std::fs::write(assets.join("logo.svg"), "<svg></svg>".repeat(200)).unwrap();
+
+        let compressor = |min_savings_bytes| {
+            Compressor::builder()
+                .dist(dist.path())
+                .min_savings_bytes(min_savings_bytes)
+                .build()
+                .unwrap()
+        };
+        let variants = || {
+            std::fs::read_dir(dist.path().join("brotli/assets"))
+                .unwrap()
+                .count()
+        };
+
+        let report = compressor(1).run().await.unwrap();
+        assert!(report.is_success(), "{:?}", report.errors);
+        assert_eq!(status(&report, "assets/logo.svg"), FileStatus::Compressed);
+        assert_eq!(variants(), 1);
+
+        let report = compressor(1).run().await.unwrap();
+        assert_eq!(status(&report, "assets/logo.svg"), FileStatus::Unchanged);
+
+        // saves about 2 kB, a variant that paid off before doesn't anymore
+        let report = compressor(10_000).run().await.unwrap();
+        assert!(report.is_success(), "{:?}", report.errors);
+        assert_eq!(
+            status(&report, "assets/logo.svg"),
+            FileStatus::BelowThreshold
+        );
+        assert_eq!(variants(), 0);
+
+        let report = compressor(10_000).run().await.unwrap();
+        assert_eq!(
+            status(&report, "assets/logo.svg"),
+            FileStatus::BelowThreshold
+        );
+        assert!(!report.changed());
+    }
+}
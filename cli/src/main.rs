@@ -1,334 +1,484 @@
 use std::{
-    iter::FilterMap,
+    num::NonZeroUsize,
     path::{Path, PathBuf},
+    process::ExitCode,
+    time::Duration,
 };
 
-use async_compression::tokio::write::BrotliEncoder;
-use clap::Parser;
-use tokio::{
-    self,
-    fs::File,
-    io::{AsyncReadExt, AsyncWriteExt},
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
+use tracing::{error, info, level_filters::LevelFilter};
+use tracing_subscriber::EnvFilter;
+use trunk_compress::{
+    Baseline, BrotliPreset, BrotliSettings, Budget, Compressor, Config, Encoding, HashAlgorithm,
+    MinSavings, Report, DEFAULT_DIST,
 };
-use tracing::{info, warn, Level};
-use tracing_subscriber::{self, FmtSubscriber};
-use walkdir::{DirEntry, IntoIter, WalkDir};
-
-const FRONTEND_DIR: &str = r"../frontend/dist/";
-
-fn identity_files() -> FilterMap<IntoIter, fn(walkdir::Result<DirEntry>) -> Option<DirEntry>> {
-    WalkDir::new(Path::new(FRONTEND_DIR).join("identity"))
-        .follow_links(true)
-        .into_iter()
-        .filter_map(|e| match e {
-            Ok(entry) => {
-                if entry.path().is_file() {
-                    Some(entry)
-                } else {
-                    None
-                }
-            }
-            Err(_) => None,
-        })
-}
 
-struct YewCompressor {
-    g: GenerationSpec,
+mod watch;
+
+use watch::{watch, WatchOptions};
+
+/// The exit codes of `trunk-compress`, so build scripts can tell what happened.
+mod exit_code {
+    /// everything is up to date
+    pub const SUCCESS: u8 = 0;
+    /// some files could not be compressed or cleaned up, the rest were
+    pub const FILE_ERRORS: u8 = 1;
+    /// invalid arguments or config, as reported by clap or when building the compressor
+    pub const USAGE: u8 = 2;
+    /// nothing could be done, e.g. the identity directory can't be created
+    pub const FATAL: u8 = 3;
+    /// `check` found compressed trees that are out of date
+    pub const OUTDATED: u8 = 4;
+    /// `verify` found compressed files that don't match their identity files
+    pub const INVALID: u8 = 5;
+    /// the compressed trees exceed one of the budgets in the config file
+    pub const OVER_BUDGET: u8 = 6;
 }
 
-impl YewCompressor {
-    fn new(g: GenerationSpec) -> Self {
-        Self { g }
-    }
-
-    async fn compress(&self) {
-        let mid_diff = {
-            let file = self.g.identity_file.clone();
-            let file = file.into_path();
+/// compress the files in the identity directory
+///
+/// Exit codes: 0 when everything is up to date, 1 when some files failed (the others are still
+/// compressed), 2 for invalid arguments or config, 3 when nothing could be done at all, 4 when
+/// `check` finds the compressed trees out of date, 5 when `verify` finds broken compressed files,
+/// 6 when a budget from the config file is exceeded.
+#[derive(Parser, Debug)]
+#[command(author="Mattsy", version, about, long_about = None)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// print what would be compressed, renamed and removed, without touching the disk
+    #[arg(global = true, long)]
+    dry_run: bool,
+
+    /// trunk's dist directory, the parent of the `identity` and compressed trees
+    /// [default: ../frontend/dist/]
+    #[arg(global = true, long, env = "TRUNK_COMPRESS_DIST")]
+    dist: Option<PathBuf>,
+
+    /// the uncompressed files written by trunk [default: <DIST>/identity]
+    #[arg(global = true, long)]
+    identity_dir: Option<PathBuf>,
+
+    /// where the `brotli`, `gzip` and `zstd` trees are written [default: <DIST>]
+    #[arg(global = true, long)]
+    output_dir: Option<PathBuf>,
+
+    /// which compressed trees to generate, can be repeated [default: br]
+    #[arg(global = true, long = "encoding", value_enum)]
+    encodings: Vec<Encoding>,
+
+    /// brotli quality, from 0 (fastest) to 11 (smallest) [default: 11]
+    #[arg(global = true, long, value_parser = clap::value_parser!(u32).range(0..=11))]
+    quality: Option<u32>,
+
+    /// log2 of the brotli window size, from 10 to 24 [default: 22]
+    #[arg(global = true, long, value_parser = clap::value_parser!(u32).range(10..=24))]
+    lgwin: Option<u32>,
+
+    /// brotli settings per file extension as EXT=QUALITY[:LGWIN], e.g. `wasm=11:24`, can be repeated
+    #[arg(global = true, long = "preset", value_name = "EXT=QUALITY[:LGWIN]")]
+    presets: Vec<BrotliPreset>,
+
+    /// how many files are compressed at once [default: number of cpus]
+    #[arg(global = true, short, long)]
+    jobs: Option<NonZeroUsize>,
+
+    /// don't write an asset's compressed variant unless it saves at least this many bytes, the
+    /// server falls back to the identity file [default: 1]
+    #[arg(global = true, long)]
+    min_savings_bytes: Option<u64>,
+
+    /// don't write an asset's compressed variant unless it saves at least this fraction of its size,
+    /// e.g. `0.1` for 10% [default: 0]
+    #[arg(global = true, long, value_parser = parse_ratio)]
+    min_savings_ratio: Option<f64>,
+
+    /// how identity files are fingerprinted, to tell whether they changed and to name assets
+    /// [default: blake3]
+    #[arg(global = true, long, value_enum)]
+    hash: Option<HashAlgorithm>,
+
+    /// compress the files matching this glob even if excluded, e.g. `assets/*.json`, can be repeated
+    #[arg(global = true, long = "include", value_name = "GLOB")]
+    includes: Vec<String>,
+
+    /// skip the files matching this glob, on top of the media files and `index.html` skipped by
+    /// default, e.g. `*.woff2`, can be repeated
+    #[arg(global = true, long = "exclude", value_name = "GLOB")]
+    excludes: Vec<String>,
+
+    /// the config file, the flags override it. For a `Trunk.toml`, its `[compress]` table
+    /// [default: ./trunk-compress.toml or ./Trunk.toml, if one exists]
+    #[arg(global = true, long)]
+    config: Option<PathBuf>,
+
+    /// print the size, ratio and time of every file along with the totals per tree, instead of
+    /// the plan of a dry run
+    #[arg(global = true, long, value_enum, value_name = "FORMAT")]
+    report: Option<ReportFormat>,
+
+    /// a `--report json` of an earlier run to compare the budgets from the config file against
+    #[arg(global = true, long, value_name = "FILE")]
+    baseline: Option<PathBuf>,
+
+    /// log more, `-vv` for everything
+    #[arg(global = true, short, long, action = ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+
+    /// log less, `-q` for warnings and errors only, `-qq` for errors only, `-qqq` for nothing
+    #[arg(global = true, short, long, action = ArgAction::Count)]
+    quiet: u8,
+
+    /// how log lines are written to stderr. `RUST_LOG` takes precedence over `-v` and `-q`, e.g.
+    /// `RUST_LOG=trunk_compress=debug`
+    #[arg(global = true, long, value_enum, value_name = "FORMAT", default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+}
 
-            let parent = file.parent().unwrap();
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum ReportFormat {
+    Json,
+    Table,
+}
 
-            pathdiff::diff_paths(parent, Path::new(FRONTEND_DIR).join("identity")).unwrap()
-        };
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum LogFormat {
+    Text,
+    /// one JSON object per line
+    Json,
+}
 
-        tokio::fs::create_dir_all(
-            Path::new(FRONTEND_DIR)
-                .join("brotli")
-                .join(mid_diff.clone()),
-        )
-        .await
-        .ok();
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    /// exit with 4 if the compressed trees are out of date with the identity directory, without
+    /// touching them
+    Check,
+    /// decode every compressed file and compare it with its identity file, and print the
+    /// mismatches, orphans and missing files as JSON
+    Verify,
+    /// show where the config is read from
+    Config {
+        /// print the config file merged with the flags, as TOML
+        #[arg(long)]
+        print: bool,
+    },
+    /// compress, then again whenever the identity directory changes
+    Watch {
+        /// how long the identity directory has to be quiet before compressing, in milliseconds
+        #[arg(long, default_value_t = 300)]
+        debounce_ms: u64,
+
+        /// bump the modification time of this file after every change, e.g. a source file of
+        /// the server for `cargo watch` to pick up
+        #[arg(long)]
+        touch: Option<PathBuf>,
+
+        /// GET this local http:// url after every change
+        #[arg(long)]
+        ping: Option<String>,
+    },
+}
 
-        let target = self.g.to_target();
+fn parse_ratio(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(ratio) if (0.0..=1.0).contains(&ratio) => Ok(ratio),
+        Ok(_) => Err("must be between 0 and 1".to_owned()),
+        Err(e) => Err(e.to_string()),
+    }
+}
 
-        info!("outputing target {:?}", target);
+impl Cli {
+    fn config_file(&self) -> Option<&Path> {
+        self.config.as_deref().or(Config::find())
+    }
 
-        let output = File::create(target).await.unwrap();
+    /// The config file with the flags applied on top.
+    fn config(&self) -> Result<Config, trunk_compress::Error> {
+        let mut config = match self.config_file() {
+            Some(path) => Config::read(path)?,
+            None => Config::default(),
+        };
 
-        let mut buf: Vec<u8> = Vec::new();
-        File::open(self.g.identity_file.path())
-            .await
-            .unwrap()
-            .read_to_end(&mut buf)
-            .await
-            .unwrap();
+        if let Some(dist) = &self.dist {
+            config.dist = Some(dist.clone());
+        }
+        if let Some(dir) = &self.identity_dir {
+            config.identity_dir = Some(dir.clone());
+        }
+        if let Some(dir) = &self.output_dir {
+            config.output_dir = Some(dir.clone());
+        }
+        if !self.encodings.is_empty() {
+            config.encodings = self.encodings.clone();
+        }
+        if let Some(hash) = self.hash {
+            config.hash = Some(hash);
+        }
+        if let Some(jobs) = self.jobs {
+            config.jobs = Some(jobs);
+        }
+        if let Some(quality) = self.quality {
+            config.brotli.quality = Some(quality);
+        }
+        if let Some(lgwin) = self.lgwin {
+            config.brotli.lgwin = Some(lgwin);
+        }
+        config.brotli.presets.extend(self.presets.iter().cloned());
+        if let Some(bytes) = self.min_savings_bytes {
+            config.min_savings.bytes = Some(bytes);
+        }
+        if let Some(ratio) = self.min_savings_ratio {
+            config.min_savings.ratio = Some(ratio);
+        }
+        config.files.include.extend(self.includes.iter().cloned());
+        config.files.exclude.extend(self.excludes.iter().cloned());
 
-        let slice = buf.as_slice();
+        Ok(config)
+    }
 
-        let mut encoder = BrotliEncoder::new(output);
-        encoder.write_all(slice).await.unwrap();
-        encoder.shutdown().await.unwrap();
+    fn log_level(&self) -> LevelFilter {
+        match (self.verbose, self.quiet) {
+            (0, 0) => LevelFilter::INFO,
+            (1, _) => LevelFilter::DEBUG,
+            (_, 0) => LevelFilter::TRACE,
+            (_, 1) => LevelFilter::WARN,
+            (_, 2) => LevelFilter::ERROR,
+            _ => LevelFilter::OFF,
+        }
     }
 }
 
-struct GenerationSpec {
-    identity_file: DirEntry,
-    hash: Option<String>,
+/// Logs go to stderr, stdout is for reports.
+fn init_logging(cli: &Cli) {
+    let filter = EnvFilter::builder()
+        .with_default_directive(cli.log_level().into())
+        .from_env_lossy();
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_ansi(false)
+        .with_writer(std::io::stderr);
+
+    let result = match cli.log_format {
+        LogFormat::Text => builder.try_init(),
+        LogFormat::Json => builder.json().try_init(),
+    };
+    if let Err(e) = result {
+        eprintln!("Unable to set global default subscriber: {e}");
+    }
 }
 
-impl GenerationSpec {
-    fn to_target(&self) -> PathBuf {
-        let identity_file_path = self.identity_file.path();
-        let mid_diff = {
-            let parent = identity_file_path.parent().unwrap();
-
-            pathdiff::diff_paths(parent, Path::new(FRONTEND_DIR).join("identity")).unwrap()
-        };
-        match self.hash {
-            Some(ref hash) => {
-                let (name, suffix) = {
-                    let split = identity_file_path
-                        .file_name()
-                        .unwrap()
-                        .to_str()
-                        .unwrap()
-                        .split('.')
-                        .collect::<Vec<_>>();
-
-                    let suffix = (*split.last().unwrap()).to_owned();
-
-                    (split[..split.len() - 1].join(""), suffix)
-                };
-
-                Path::new(FRONTEND_DIR)
-                    .join("brotli")
-                    .join(mid_diff)
-                    .join(format!("{}-{}.{}.br", name, hash, suffix,))
-            }
-            None => Path::new(FRONTEND_DIR)
-                .join("brotli")
-                .join(mid_diff)
-                .join(format!(
-                    "{}.br",
-                    identity_file_path.file_name().unwrap().to_string_lossy(),
-                )),
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    init_logging(&cli);
+    let command = cli.command.clone();
+    let report_format = cli.report;
+
+    let config = match cli.config() {
+        Ok(config) => config,
+        Err(e) => {
+            error!("{e}");
+            return ExitCode::from(exit_code::USAGE);
         }
-    }
-}
+    };
 
-async fn compress() {
-    let identity_dir = Path::new(FRONTEND_DIR).join("identity");
-    if !identity_dir.exists() {
-        info!("identity directory does not exist, creating");
-        tokio::fs::create_dir_all(identity_dir).await.ok();
-    }
-    let brotli_dir = Path::new(FRONTEND_DIR).join("brotli");
-    if !brotli_dir.exists() {
-        info!("brotli directory does not exist, creating");
-        tokio::fs::create_dir_all(brotli_dir).await.ok();
+    if let Some(Command::Config { print }) = command {
+        return print_config(&cli, config, print);
     }
-    // todo: use the above variables consistently across the codebase
-
-    let identity_files: Vec<DirEntry> = identity_files().collect();
-
-    let compression_dir = Path::new(FRONTEND_DIR).join("brotli");
-
-    let mut to_be_generated: Vec<GenerationSpec> = identity_files
-        .into_iter()
-        .filter_map(|e| {
-            // ignore compressed images, audio, and video
-            let filename = e.file_name().to_str().unwrap();
-
-            if filename.ends_with(".png")
-                || filename.ends_with(".jpg")
-                || filename.ends_with(".jpeg")
-                || filename.ends_with(".gif")
-                || filename.ends_with(".ico")
-                || filename.ends_with(".mp3")
-                || filename.ends_with(".mp4")
-                || filename.ends_with(".webm")
-                || filename.ends_with(".ogg")
-                || filename.ends_with(".wav")
-                || filename == "index.html"
-            {
-                return None;
-            }
 
-            Some(GenerationSpec {
-                hash: if e
-                    .path()
-                    .starts_with(Path::new(FRONTEND_DIR).join("identity").join("assets"))
-                {
-                    Some(format!(
-                        "{:?}",
-                        md5::compute(std::fs::read(e.path()).unwrap())
-                    ))
-                } else {
-                    None
-                },
-                identity_file: e,
-            })
-        })
-        .collect();
-
-    let mut deleting_dirs: Vec<PathBuf> = Vec::new();
-    let mut deleting_files: Vec<PathBuf> = Vec::new();
-
-    for old_entry in WalkDir::new(&compression_dir)
-        .into_iter()
-        .filter_map(Result::ok)
-    {
-        if old_entry.path().is_dir() {
-            let corresponding_identity = Path::new(FRONTEND_DIR).join("identity").join(
-                old_entry
-                    .path()
-                    .strip_prefix(&compression_dir)
-                    .unwrap()
-                    .to_str()
-                    .unwrap(),
-            );
-
-            if !corresponding_identity.exists() || !corresponding_identity.is_dir() {
-                info!("removing outdated directory {:?}", old_entry.path());
-                deleting_dirs.push(old_entry.path().to_owned());
-            }
+    let builder = Compressor::builder()
+        .config(&config)
+        .dry_run(cli.dry_run || matches!(command, Some(Command::Check)));
 
-            continue;
+    let baseline = match cli.baseline.as_deref().map(Baseline::read).transpose() {
+        Ok(baseline) => baseline,
+        Err(e) => {
+            error!("{e}");
+            return ExitCode::from(exit_code::USAGE);
         }
+    };
 
-        let filename = old_entry.file_name().to_str().unwrap();
+    let compressor = match builder.build() {
+        Ok(compressor) => compressor,
+        Err(e) => {
+            error!("{e}");
+            return ExitCode::from(exit_code::USAGE);
+        }
+    };
 
-        let Some(stripped) = filename.strip_suffix(".br") else {
-            warn!("file {:?} does not have a .br suffix", old_entry.path());
-            continue;
+    if let Some(Command::Watch {
+        debounce_ms,
+        touch,
+        ping,
+    }) = command
+    {
+        let options = WatchOptions {
+            debounce: Duration::from_millis(debounce_ms),
+            touch,
+            ping,
         };
-
-        let corresponding_identity = if old_entry
-            .path()
-            .starts_with(Path::new(FRONTEND_DIR).join("brotli").join("assets"))
-        {
-            // each compressed asset looks like name-hash.suffix.br
-            // aside from the .br suffix, we also need to strip the hash
-
-            let original_suffix = Path::new(stripped)
-                .extension()
-                .unwrap()
-                .to_str()
-                .unwrap()
-                .to_owned();
-
-            let hash = stripped
-                .split('-')
-                .last()
-                .unwrap()
-                .split('.')
-                .next()
-                .unwrap();
-
-            let split = stripped.split('-').collect::<Vec<_>>();
-
-            if split.len() == 1 {
-                warn!(
-                    "file {:?} does not have a hash suffix, skipping",
-                    old_entry.path()
-                );
-                continue;
+        return match watch(&compressor, &options).await {
+            Ok(()) => ExitCode::from(exit_code::SUCCESS),
+            Err(e) => {
+                error!("{e:#}");
+                ExitCode::from(exit_code::FATAL)
             }
+        };
+    }
 
-            let mid_diff = {
-                pathdiff::diff_paths(
-                    old_entry.path().parent().unwrap(),
-                    Path::new(FRONTEND_DIR).join("brotli"),
-                )
-                .unwrap()
-            };
-
-            let corresponding_identity = Path::new(FRONTEND_DIR)
-                .join("identity")
-                .join(mid_diff)
-                .join(format!(
-                    "{}.{original_suffix}",
-                    split[..split.len() - 1].join("")
-                ));
-
-            if corresponding_identity.is_file()
-                && format!(
-                    "{:?}",
-                    md5::compute(std::fs::read(corresponding_identity.clone()).unwrap())
-                ) == hash
-            {
-                Some(corresponding_identity)
-            } else {
-                info!(
-                    "removing outdated asset {:?} because of hash mismatch",
-                    old_entry.path()
+    if let Some(Command::Verify) = command {
+        return match compressor.verify().await {
+            Ok(verification) => {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&verification)
+                        .expect("verifications always serialize")
                 );
-                deleting_files.push(old_entry.path().to_owned());
-                None
+                if verification.is_ok() {
+                    ExitCode::from(exit_code::SUCCESS)
+                } else {
+                    error!(
+                        "{} problem(s) in {} compressed file(s)",
+                        verification.problems.len(),
+                        verification.checked
+                    );
+                    ExitCode::from(exit_code::INVALID)
+                }
             }
-        } else {
-            let identity_file = Path::new(FRONTEND_DIR).join("identity").join(stripped);
-            if identity_file.is_file() {
-                Some(identity_file)
-            } else {
-                info!(
-                    "removing outdated file {:?} because can't find identity file",
-                    old_entry.path()
-                );
-                deleting_files.push(old_entry.path().to_owned());
-                None
+            Err(e) => {
+                error!("{e}");
+                ExitCode::from(exit_code::FATAL)
             }
         };
-
-        if let Some(corresponding_identity) = corresponding_identity {
-            // remove from to_be_generated
-            to_be_generated.retain(|e| e.identity_file.path() != corresponding_identity.as_path());
-        };
     }
 
-    for dir in deleting_dirs {
-        std::fs::remove_dir_all(dir).ok();
-    }
-    for file in deleting_files {
-        std::fs::remove_file(file).ok();
+    match compressor.run().await {
+        Ok(report) if report.errors.is_empty() && matches!(command, Some(Command::Check)) => {
+            print_report(&report, report_format);
+            if report.changed() {
+                error!("the compressed trees are out of date");
+                ExitCode::from(exit_code::OUTDATED)
+            } else {
+                ExitCode::from(exit_code::SUCCESS)
+            }
+        }
+        Ok(report) if report.errors.is_empty() => {
+            if cli.dry_run || report_format.is_some() {
+                print_report(&report, report_format);
+            }
+            if cli.dry_run {
+                // nothing was compressed, so there is nothing to measure
+                return ExitCode::from(exit_code::SUCCESS);
+            }
+            check_budgets(&report, &config.budgets, baseline.as_ref())
+        }
+        Ok(report) => {
+            if report_format.is_some() {
+                print_report(&report, report_format);
+            }
+            error!(
+                "{} error(s), {} file(s) compressed:",
+                report.errors.len(),
+                report.compressed.len()
+            );
+            for e in &report.errors {
+                error!("  {e}");
+            }
+            ExitCode::from(exit_code::FILE_ERRORS)
+        }
+        Err(e) => {
+            error!("{e}");
+            ExitCode::from(exit_code::FATAL)
+        }
     }
+}
 
-    // compress the to_be_generated files
+/// The config file in use, or with `print` the effective config on stdout.
+fn print_config(cli: &Cli, mut config: Config, print: bool) -> ExitCode {
+    if !print {
+        match cli.config_file() {
+            Some(path) => println!("{}", path.display()),
+            None => info!("no config file, using the defaults and the flags"),
+        }
+        return ExitCode::from(exit_code::SUCCESS);
+    }
 
-    for f in to_be_generated {
-        let file_name = f.identity_file.file_name().to_str().unwrap().to_owned();
-        YewCompressor::new(f).compress().await;
-        info!("Done compressing {file_name}");
+    config.dist.get_or_insert_with(|| DEFAULT_DIST.into());
+    if config.encodings.is_empty() {
+        config.encodings.push(Encoding::Brotli);
+    }
+    let BrotliSettings { quality, lgwin } = BrotliSettings::default();
+    let MinSavings { bytes, ratio } = MinSavings::default();
+    config.hash.get_or_insert_with(HashAlgorithm::default);
+    config.brotli.quality.get_or_insert(quality);
+    config.brotli.lgwin.get_or_insert(lgwin);
+    config.min_savings.bytes.get_or_insert(bytes);
+    config.min_savings.ratio.get_or_insert(ratio);
+    match toml::to_string_pretty(&config) {
+        Ok(toml) => {
+            print!("{toml}");
+            ExitCode::from(exit_code::SUCCESS)
+        }
+        Err(e) => {
+            error!("failed to print the config: {e}");
+            ExitCode::from(exit_code::FATAL)
+        }
     }
 }
 
-/// compress the files in the identity directory
-#[derive(Parser, Debug)]
-#[command(author="Mattsy", version, about, long_about = None)]
-struct Cli {}
+/// Logs how every budget fared, with the sizes of the files that exceed one.
+fn check_budgets(report: &Report, budgets: &[Budget], baseline: Option<&Baseline>) -> ExitCode {
+    let outcomes = match report.check_budgets(budgets, baseline) {
+        Ok(outcomes) => outcomes,
+        Err(e) => {
+            error!("{e}");
+            return ExitCode::from(exit_code::USAGE);
+        }
+    };
 
-#[tokio::main]
-async fn main() {
-    let subscriber = FmtSubscriber::builder()
-        .with_max_level(Level::TRACE)
-        .with_ansi(false)
-        .finish();
+    let mut exceeded = 0;
+    for outcome in &outcomes {
+        if outcome.exceeded() {
+            exceeded += 1;
+            error!("budget exceeded: {outcome}");
+        } else {
+            info!(
+                "within budget: {} ({}): {} bytes",
+                outcome.budget.files, outcome.encoding, outcome.bytes
+            );
+        }
+    }
 
-    tracing::subscriber::set_global_default(subscriber)
-        .map_err(|_err| eprintln!("Unable to set global default subscriber"))
-        .unwrap();
+    if exceeded > 0 {
+        error!("{exceeded} budget(s) exceeded");
+        ExitCode::from(exit_code::OVER_BUDGET)
+    } else {
+        ExitCode::from(exit_code::SUCCESS)
+    }
+}
 
-    let _ = Cli::parse();
+/// The report in the requested format on stdout, or the plan of a dry run.
+fn print_report(report: &Report, format: Option<ReportFormat>) {
+    match format {
+        Some(ReportFormat::Json) => println!(
+            "{}",
+            serde_json::to_string_pretty(&report.summary()).expect("summaries always serialize")
+        ),
+        Some(ReportFormat::Table) => print!("{}", report.summary()),
+        None => print_plan(report),
+    }
+}
 
-    compress().await;
+/// The outcome of a dry run on stdout, one line per file.
+fn print_plan(report: &Report) {
+    for path in &report.pending {
+        println!("compress {}", path.display());
+    }
+    for (from, to) in &report.renamed {
+        println!("rename {} -> {}", from.display(), to.display());
+    }
+    for path in &report.removed {
+        println!("remove {}", path.display());
+    }
 }
@@ -0,0 +1,212 @@
+use std::{collections::BTreeMap, path::Path};
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{
+    atomic::write_atomic,
+    compressor::{GenerationSpec, MinSavings},
+    error::{relative, Error},
+    hash::HashAlgorithm,
+    BrotliSettings, Dirs,
+};
+
+/// Written at the root of every compressed tree, so servers don't have to guess which
+/// compressed file belongs to which identity file.
+pub(crate) const MANIFEST: &str = "manifest.json";
+
+/// Also the cache that makes runs incremental: an identity file is only hashed again when its
+/// size or modification time changed, and only compressed again when its hash or the settings
+/// changed.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct Manifest {
+    /// what the `hash` of every entry was computed with
+    #[serde(default)]
+    pub(crate) hash: HashAlgorithm,
+    /// keyed by the path relative to the identity directory, e.g. `assets/logo.svg`
+    pub(crate) files: BTreeMap<String, ManifestEntry>,
+    /// files whose compressed variant didn't pay off, so they aren't compressed again until
+    /// they or the settings change
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) skipped: BTreeMap<String, SkippedEntry>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ManifestEntry {
+    /// path relative to the compressed tree, e.g. `assets/logo-<hash>.svg.br`
+    pub(crate) output: String,
+    #[serde(flatten)]
+    pub(crate) source: Source,
+    /// what the file was compressed with, only recorded in the brotli tree
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) brotli: Option<BrotliSettings>,
+    /// what the file had to save to get written, only recorded for `assets`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    min_savings: Option<MinSavings>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct SkippedEntry {
+    #[serde(flatten)]
+    source: Source,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    brotli: Option<BrotliSettings>,
+    min_savings: Option<MinSavings>,
+    /// what the compressed variant would have weighed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) compressed_size: Option<u64>,
+}
+
+/// What an identity file looked like when it was compressed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Source {
+    pub(crate) size: u64,
+    /// nanoseconds since the unix epoch, if the platform has it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) modified: Option<u64>,
+    pub(crate) hash: String,
+}
+
+impl ManifestEntry {
+    fn new(dirs: &Dirs, g: &GenerationSpec) -> Result<Self, Error> {
+        let output = relative(&g.target, &dirs.tree(g.encoding))?;
+        Ok(Self {
+            output: manifest_path(output),
+            source: g.source.clone(),
+            brotli: g.brotli,
+            min_savings: g.min_savings,
+        })
+    }
+
+    /// the size and modification time don't matter once the hash is known
+    fn same_input_and_settings(&self, other: &Self) -> bool {
+        self.source.hash == other.source.hash
+            && self.brotli == other.brotli
+            && self.min_savings == other.min_savings
+    }
+}
+
+impl SkippedEntry {
+    fn new(g: &GenerationSpec, compressed_size: Option<u64>) -> Self {
+        Self {
+            source: g.source.clone(),
+            brotli: g.brotli,
+            min_savings: g.min_savings,
+            compressed_size,
+        }
+    }
+
+    /// the size and modification time don't matter once the hash is known
+    fn matches(&self, other: &Self) -> bool {
+        self.source.hash == other.source.hash
+            && self.brotli == other.brotli
+            && self.min_savings == other.min_savings
+    }
+}
+
+/// `/`-separated, so the manifest reads the same on every platform
+pub(crate) fn manifest_path(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+impl Manifest {
+    pub(crate) fn new(hash: HashAlgorithm) -> Self {
+        Self {
+            hash,
+            ..Self::default()
+        }
+    }
+
+    /// The hash recorded for `key`, if the file still has the size and modification time it had
+    /// back then.
+    pub(crate) fn cached_hash(
+        &self,
+        hash: HashAlgorithm,
+        key: &str,
+        size: u64,
+        modified: Option<u64>,
+    ) -> Option<&str> {
+        if self.hash != hash || modified.is_none() {
+            return None;
+        }
+
+        let source = self
+            .files
+            .get(key)
+            .map(|e| &e.source)
+            .or_else(|| self.skipped.get(key).map(|e| &e.source))?;
+
+        (source.size == size && source.modified == modified).then_some(source.hash.as_str())
+    }
+
+    /// Records that `g` will be written.
+    pub(crate) fn insert(&mut self, dirs: &Dirs, g: &GenerationSpec) -> Result<(), Error> {
+        self.skipped.remove(&g.key);
+        self.files
+            .insert(g.key.clone(), ManifestEntry::new(dirs, g)?);
+        Ok(())
+    }
+
+    /// Records that `g` wasn't worth writing.
+    pub(crate) fn skip(&mut self, g: &GenerationSpec, compressed_size: Option<u64>) {
+        self.files.remove(&g.key);
+        self.skipped
+            .insert(g.key.clone(), SkippedEntry::new(g, compressed_size));
+    }
+
+    /// The entry of a previous run that found `g` not worth writing, with the same input and
+    /// settings.
+    pub(crate) fn was_skipped(&self, g: &GenerationSpec) -> Option<&SkippedEntry> {
+        self.skipped
+            .get(&g.key)
+            .filter(|e| e.matches(&SkippedEntry::new(g, None)))
+    }
+
+    /// Whether a previous run wrote `g` from the same input with the same settings.
+    pub(crate) fn was_written(&self, dirs: &Dirs, g: &GenerationSpec) -> Result<bool, Error> {
+        let Some(previous) = self.files.get(&g.key) else {
+            return Ok(false);
+        };
+        let current = ManifestEntry::new(dirs, g)?;
+
+        Ok(previous.output == current.output && previous.same_input_and_settings(&current))
+    }
+
+    /// Where a previous run wrote `g` from the same input with the same settings, if only the
+    /// name changed since, e.g. along with the naming scheme.
+    pub(crate) fn renamed_output(
+        &self,
+        dirs: &Dirs,
+        g: &GenerationSpec,
+    ) -> Result<Option<&str>, Error> {
+        let Some(previous) = self.files.get(&g.key) else {
+            return Ok(None);
+        };
+        let current = ManifestEntry::new(dirs, g)?;
+
+        Ok(
+            (previous.output != current.output && previous.same_input_and_settings(&current))
+                .then_some(previous.output.as_str()),
+        )
+    }
+
+    /// The manifest a previous run left in `tree`, empty if there is none.
+    pub(crate) async fn read(tree: &Path) -> Self {
+        match tokio::fs::read(tree.join(MANIFEST)).await {
+            Ok(json) => serde_json::from_slice(&json).unwrap_or_else(|e| {
+                warn!("ignoring unreadable manifest in {:?}: {e}", tree);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub(crate) async fn write(&self, tree: &Path) -> Result<(), Error> {
+        let path = tree.join(MANIFEST);
+        let json = serde_json::to_string_pretty(self).expect("manifests always serialize");
+        write_atomic(&path, json.as_bytes()).map_err(|source| Error::Write { path, source })
+    }
+}
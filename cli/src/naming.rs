@@ -0,0 +1,208 @@
+use crate::hash::NAME_HASH_LEN;
+
+/// The name of a compressed asset before the encoding suffix: `<stem>-<hash>.<extension>`, or
+/// `<stem>-<hash>` for files without an extension.
+///
+/// Stem and extension are split like [`Path::file_stem`](std::path::Path::file_stem) and
+/// [`Path::extension`](std::path::Path::extension) do, so `jquery.min.js` becomes
+/// `jquery.min-<hash>.js` and `my-logo.svg` becomes `my-logo-<hash>.svg`. Since the hash is
+/// always [`NAME_HASH_LEN`] lowercase hex digits, [`HashedName::parse`] gets back exactly what
+/// [`HashedName::format`] was given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct HashedName<'a> {
+    pub(crate) stem: &'a str,
+    pub(crate) hash: &'a str,
+    pub(crate) extension: Option<&'a str>,
+}
+
+/// Everything after the last dot is the extension, unless that leaves no stem, as in
+/// `.gitignore`, or the name is `..`.
+fn split_extension(file_name: &str) -> (&str, Option<&str>) {
+    match file_name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() && file_name != ".." => (stem, Some(extension)),
+        _ => (file_name, None),
+    }
+}
+
+impl<'a> HashedName<'a> {
+    /// `hash` is cut to [`NAME_HASH_LEN`] digits
+    pub(crate) fn new(file_name: &'a str, hash: &'a str) -> Self {
+        let (stem, extension) = split_extension(file_name);
+
+        Self {
+            stem,
+            hash: &hash[..NAME_HASH_LEN.min(hash.len())],
+            extension,
+        }
+    }
+
+    pub(crate) fn format(&self) -> String {
+        match self.extension {
+            Some(extension) => format!("{}-{}.{extension}", self.stem, self.hash),
+            None => format!("{}-{}", self.stem, self.hash),
+        }
+    }
+
+    /// `None` for names [`HashedName::format`] can't have produced, e.g. the ones from before it
+    /// existed: `jquerymin-<32 digit md5>.js`.
+    pub(crate) fn parse(name: &'a str) -> Option<Self> {
+        // an extension never has a dot, so the hash is right before the last one if there is
+        // an extension at all, and at the end otherwise: `..-<hash>` and `.hidden-<hash>` have
+        // dots, but no extension
+        let with_extension = name.rsplit_once('.').and_then(|(hashed_stem, extension)| {
+            let (stem, hash) = split_hash(hashed_stem)?;
+            Some(Self {
+                stem,
+                hash,
+                extension: Some(extension),
+            })
+        });
+
+        with_extension.or_else(|| {
+            let (stem, hash) = split_hash(name)?;
+            Some(Self {
+                stem,
+                hash,
+                extension: None,
+            })
+        })
+    }
+}
+
+/// `<stem>-<hash>` into a non-empty stem and a lowercase hex hash
+fn split_hash(hashed_stem: &str) -> Option<(&str, &str)> {
+    let (stem, hash) =
+        hashed_stem.split_at_checked(hashed_stem.len().checked_sub(NAME_HASH_LEN)?)?;
+    let stem = stem.strip_suffix('-')?;
+    if stem.is_empty() || !hash.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f')) {
+        return None;
+    }
+    Some((stem, hash))
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    const HASH: &str = "0123456789abcdef0123456789abcdef";
+
+    /// Every combination of a few stems and extensions, like the ones trunk and asset pipelines
+    /// produce, plus the odd ones.
+    fn file_names() -> Vec<String> {
+        let stems = [
+            "a",
+            "logo",
+            "my-logo",
+            "my--logo-",
+            "jquery.min",
+            "a.b.c.d",
+            "frontend-3c585650ceac9d6d",
+            ".hidden",
+            "..",
+            "..x",
+            "dash-0123456789abcdef",
+        ];
+        let extensions = [None, Some("js"), Some("tar.gz"), Some("br"), Some("")];
+
+        let mut names = Vec::new();
+        for stem in stems {
+            for extension in extensions {
+                names.push(match extension {
+                    Some(extension) => format!("{stem}.{extension}"),
+                    None => stem.to_owned(),
+                });
+            }
+        }
+        names.extend(["LICENSE", "file.", "a.", ".gitignore", "x-y.z-w"].map(String::from));
+        names
+    }
+
+    #[test]
+    fn parse_inverts_format() {
+        for name in file_names() {
+            let hashed = HashedName::new(&name, HASH);
+            let formatted = hashed.format();
+            assert_eq!(
+                HashedName::parse(&formatted),
+                Some(hashed),
+                "{name:?} formatted as {formatted:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn format_keeps_the_file_name_around_the_hash() {
+        for name in file_names() {
+            let formatted = HashedName::new(&name, HASH).format();
+            let hash = format!("-{}", &HASH[..NAME_HASH_LEN]);
+            let (before, after) = formatted.rsplit_once(&hash).unwrap();
+            assert_eq!(format!("{before}{after}"), name);
+        }
+    }
+
+    /// Any file name, with the odd ones made likely: names already ending in a hash, with or
+    /// without an extension, and names made of dots only.
+    fn any_file_name() -> impl Strategy<Value = String> {
+        prop_oneof![
+            "[^/]+",
+            "[^/]*-[0-9a-f]{16}(\\.[^/.]*)?",
+            "\\.{1,4}[^/]{0,3}",
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn any_name_round_trips(name in any_file_name(), hash in "[0-9a-f]{16,64}") {
+            let hashed = HashedName::new(&name, &hash);
+            let formatted = hashed.format();
+            prop_assert_eq!(
+                HashedName::parse(&formatted),
+                Some(hashed),
+                "formatted as {:?}",
+                formatted
+            );
+
+            let unhashed = match hashed.extension {
+                Some(extension) => format!("{}.{extension}", hashed.stem),
+                None => hashed.stem.to_owned(),
+            };
+            prop_assert_eq!(&unhashed, &name);
+            prop_assert_eq!(hashed.hash, &hash[..NAME_HASH_LEN]);
+        }
+    }
+
+    #[test]
+    fn hash_is_cut() {
+        let hashed = HashedName::new("app.js", HASH);
+        assert_eq!(hashed.hash, &HASH[..NAME_HASH_LEN]);
+        assert_eq!(hashed.format(), "app-0123456789abcdef.js");
+    }
+
+    #[test]
+    fn old_scheme_names_do_not_parse() {
+        for name in [
+            "jquerymin-5bdb6c2c8f2b1e6e6b0c5d4a3f2e1d0c.js",
+            "mylogo-5bdb6c2c8f2b1e6e6b0c5d4a3f2e1d0c.svg",
+            "LICENSE-5bdb6c2c8f2b1e6e6b0c5d4a3f2e1d0c",
+        ] {
+            assert_eq!(HashedName::parse(name), None, "{name:?}");
+        }
+    }
+
+    #[test]
+    fn unhashed_names_do_not_parse() {
+        for name in [
+            "app.js",
+            "-0123456789abcdef.js",
+            "app-0123456789ABCDEF.js",
+            "app-0123456789abcdeg.js",
+            "app_0123456789abcdef.js",
+            "app-0123456789abcde.js",
+            "",
+        ] {
+            assert_eq!(HashedName::parse(name), None, "{name:?}");
+        }
+    }
+}
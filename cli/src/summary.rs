@@ -0,0 +1,263 @@
+use std::{fmt, time::Duration};
+
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::Encoding;
+
+/// What happened to one identity file in one compressed tree, see [`Report::files`](crate::Report::files).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileReport {
+    /// relative to the identity directory, e.g. `assets/logo.svg`
+    pub path: String,
+    /// `None` for files that aren't compressed at all
+    pub encoding: Option<Encoding>,
+    pub status: FileStatus,
+    pub original_bytes: u64,
+    /// `None` when there is no compressed file to measure, e.g. in a dry run
+    pub compressed_bytes: Option<u64>,
+    /// compressed over original bytes
+    pub ratio: Option<f64>,
+    /// how long compressing took, `None` unless it happened in this run
+    #[serde(
+        rename = "elapsed_ms",
+        serialize_with = "serialize_millis",
+        skip_deserializing
+    )]
+    pub elapsed: Option<Duration>,
+}
+
+impl FileReport {
+    pub(crate) fn new(
+        path: &str,
+        encoding: Option<Encoding>,
+        status: FileStatus,
+        original_bytes: u64,
+        compressed_bytes: Option<u64>,
+    ) -> Self {
+        Self {
+            path: path.to_owned(),
+            encoding,
+            status,
+            original_bytes,
+            compressed_bytes,
+            ratio: compressed_bytes.map(|c| ratio(original_bytes, c)),
+            elapsed: None,
+        }
+    }
+
+    pub(crate) fn elapsed(mut self, elapsed: Duration) -> Self {
+        self.elapsed = Some(elapsed);
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileStatus {
+    Compressed,
+    /// the compressed file from a previous run is still up to date
+    Unchanged,
+    /// the compressed file from a previous run only got a new name
+    Renamed,
+    /// would have been compressed, in a dry run
+    Pending,
+    /// the compressed file didn't meet the [`MinSavings`](crate::MinSavings), now or in a
+    /// previous run
+    BelowThreshold,
+    /// matched one of the [`DEFAULT_EXCLUDES`](crate::DEFAULT_EXCLUDES): a media type that is
+    /// compressed already, or `index.html`
+    ExcludedByDefault,
+    /// matched a configured exclude pattern
+    Excluded,
+}
+
+impl FileStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FileStatus::Compressed => "compressed",
+            FileStatus::Unchanged => "unchanged",
+            FileStatus::Renamed => "renamed",
+            FileStatus::Pending => "pending",
+            FileStatus::BelowThreshold => "below_threshold",
+            FileStatus::ExcludedByDefault => "excluded_by_default",
+            FileStatus::Excluded => "excluded",
+        }
+    }
+}
+
+/// The size of one compressed tree as it is served, counting every file whose size is known.
+/// Files whose compressed variant didn't pay off count with their original size, like in
+/// [`Report::check_budgets`](crate::Report::check_budgets).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Totals {
+    pub encoding: Encoding,
+    pub files: usize,
+    pub original_bytes: u64,
+    /// as served, with the original size of the files that aren't compressed
+    pub compressed_bytes: u64,
+    pub ratio: f64,
+    /// time spent compressing in this run, summed over the files
+    #[serde(rename = "elapsed_ms", serialize_with = "serialize_millis")]
+    pub elapsed: Duration,
+}
+
+/// Every file of a [`Report`](crate::Report) along with the totals per tree. Serializes to JSON
+/// for CI, and displays as a table for humans.
+#[derive(Debug, Clone, Serialize)]
+pub struct Summary<'a> {
+    pub files: &'a [FileReport],
+    pub totals: Vec<Totals>,
+}
+
+impl<'a> Summary<'a> {
+    pub(crate) fn new(files: &'a [FileReport]) -> Self {
+        let mut totals: Vec<Totals> = Vec::new();
+        for file in files {
+            let (Some(encoding), Some(served_bytes)) = (file.encoding, served_size(file)) else {
+                continue;
+            };
+            let i = match totals.iter().position(|t| t.encoding == encoding) {
+                Some(i) => i,
+                None => {
+                    totals.push(Totals {
+                        encoding,
+                        files: 0,
+                        original_bytes: 0,
+                        compressed_bytes: 0,
+                        ratio: 0.0,
+                        elapsed: Duration::ZERO,
+                    });
+                    totals.len() - 1
+                }
+            };
+            let t = &mut totals[i];
+            t.files += 1;
+            t.original_bytes += file.original_bytes;
+            t.compressed_bytes += served_bytes;
+            t.elapsed += file.elapsed.unwrap_or_default();
+        }
+        for t in &mut totals {
+            t.ratio = ratio(t.original_bytes, t.compressed_bytes);
+        }
+        totals.sort_by_key(|t| t.encoding);
+
+        Self { files, totals }
+    }
+}
+
+impl fmt::Display for Summary<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let header = [
+            "file",
+            "encoding",
+            "original",
+            "compressed",
+            "ratio",
+            "time",
+            "status",
+        ];
+        let mut rows: Vec<[String; 7]> = self
+            .files
+            .iter()
+            .map(|file| {
+                [
+                    file.path.clone(),
+                    file.encoding.map_or("-", |e| e.dir_name()).to_owned(),
+                    file.original_bytes.to_string(),
+                    optional(file.compressed_bytes),
+                    file.ratio.map_or_else(|| "-".to_owned(), percent),
+                    file.elapsed
+                        .map_or_else(|| "-".to_owned(), |e| format!("{e:.2?}")),
+                    file.status.as_str().to_owned(),
+                ]
+            })
+            .collect();
+        let files = rows.len();
+        rows.extend(self.totals.iter().map(|t| {
+            [
+                format!("total ({} files)", t.files),
+                t.encoding.dir_name().to_owned(),
+                t.original_bytes.to_string(),
+                t.compressed_bytes.to_string(),
+                percent(t.ratio),
+                if t.elapsed.is_zero() {
+                    "-".to_owned()
+                } else {
+                    format!("{:.2?}", t.elapsed)
+                },
+                String::new(),
+            ]
+        }));
+
+        let mut widths = header.map(str::len);
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.len());
+            }
+        }
+
+        let line = |f: &mut fmt::Formatter<'_>, cells: [&str; 7]| {
+            // the path and the names left-aligned, the numbers right-aligned
+            let mut line = String::new();
+            for (i, (cell, width)) in cells.iter().zip(widths).enumerate() {
+                if i > 0 {
+                    line.push_str("  ");
+                }
+                if matches!(i, 0 | 1 | 6) {
+                    line.push_str(&format!("{cell:<width$}"));
+                } else {
+                    line.push_str(&format!("{cell:>width$}"));
+                }
+            }
+            writeln!(f, "{}", line.trim_end())
+        };
+
+        line(f, header)?;
+        for (i, row) in rows.iter().enumerate() {
+            if i == files {
+                line(
+                    f,
+                    widths.map(|w| "-".repeat(w)).each_ref().map(String::as_str),
+                )?;
+            }
+            line(f, row.each_ref().map(String::as_str))?;
+        }
+        Ok(())
+    }
+}
+
+/// How many bytes the file takes up as served: compressed, or as is when there is no compressed
+/// variant. `None` in a dry run, before anything is compressed.
+pub(crate) fn served_size(file: &FileReport) -> Option<u64> {
+    match file.status {
+        FileStatus::Compressed | FileStatus::Unchanged | FileStatus::Renamed => {
+            file.compressed_bytes
+        }
+        FileStatus::BelowThreshold | FileStatus::ExcludedByDefault | FileStatus::Excluded => {
+            Some(file.original_bytes)
+        }
+        FileStatus::Pending => None,
+    }
+}
+
+fn ratio(original_bytes: u64, compressed_bytes: u64) -> f64 {
+    compressed_bytes as f64 / original_bytes.max(1) as f64
+}
+
+fn percent(ratio: f64) -> String {
+    format!("{:.1}%", ratio * 100.0)
+}
+
+fn optional(bytes: Option<u64>) -> String {
+    bytes.map_or_else(|| "-".to_owned(), |b| b.to_string())
+}
+
+fn serialize_millis<S: Serializer, D: Into<Option<Duration>> + Copy>(
+    duration: &D,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match (*duration).into() {
+        Some(d) => serializer.serialize_f64(d.as_secs_f64() * 1000.0),
+        None => serializer.serialize_none(),
+    }
+}
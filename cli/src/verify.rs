@@ -0,0 +1,182 @@
+use std::{collections::HashSet, io::Read as _, path::PathBuf};
+
+use serde::Serialize;
+use walkdir::WalkDir;
+
+use crate::{
+    compressor::{identity_files, GenerationSpec, Input},
+    encoding::decoder,
+    error::relative,
+    manifest::{manifest_path, Manifest, Source, MANIFEST},
+    Compressor, Encoding, Error,
+};
+
+/// The outcome of [`Compressor::verify`], serializable for CI.
+#[derive(Debug, Default, Serialize)]
+pub struct Verification {
+    /// how many compressed files were decoded and compared
+    pub checked: usize,
+    pub problems: Vec<Problem>,
+}
+
+impl Verification {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Problem {
+    /// the compressed file doesn't decode to its identity file, or has the wrong name
+    Mismatch {
+        encoding: Encoding,
+        identity: PathBuf,
+        output: PathBuf,
+        reason: String,
+    },
+    /// a file in a compressed tree that no identity file accounts for
+    Orphan { encoding: Encoding, output: PathBuf },
+    /// an identity file that should have been compressed but wasn't
+    Missing {
+        encoding: Encoding,
+        identity: PathBuf,
+        expected: PathBuf,
+    },
+    /// a file that couldn't be read at all
+    Unreadable { path: PathBuf, reason: String },
+}
+
+pub(crate) async fn verify(settings: &Compressor) -> Result<Verification, Error> {
+    let dirs = &settings.dirs;
+    let mut verification = Verification::default();
+
+    let mut identity = Vec::new();
+    for entry in identity_files(dirs) {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                verification.problems.push(Problem::Unreadable {
+                    path: dirs.identity.clone(),
+                    reason: e.to_string(),
+                });
+                continue;
+            }
+        };
+        let key = manifest_path(relative(entry.path(), &dirs.identity)?);
+        if !settings.filter.should_compress(&key) {
+            continue;
+        }
+        match std::fs::read(entry.path()) {
+            Ok(data) => identity.push((entry, key, data)),
+            Err(e) => verification.problems.push(Problem::Unreadable {
+                path: entry.path().to_owned(),
+                reason: e.to_string(),
+            }),
+        }
+    }
+
+    // the configured trees, and whatever else a previous run left behind
+    let encodings = Encoding::ALL
+        .into_iter()
+        .filter(|&e| settings.encodings.contains(&e) || dirs.tree(e).exists());
+
+    for encoding in encodings {
+        let tree = dirs.tree(encoding);
+        let manifest = Manifest::read(&tree).await;
+        let mut accounted = HashSet::from([tree.join(MANIFEST)]);
+
+        for (entry, key, data) in &identity {
+            if manifest.skipped.contains_key(key) {
+                continue;
+            }
+
+            // where `compress()` would put it now
+            let input = Input {
+                entry: entry.clone(),
+                key: key.clone(),
+                source: Source {
+                    size: data.len() as u64,
+                    modified: None,
+                    hash: manifest.hash.hash(data),
+                },
+            };
+            let brotli = (encoding == Encoding::Brotli).then(|| {
+                settings
+                    .brotli
+                    .for_file(&entry.file_name().to_string_lossy())
+            });
+            let expected =
+                GenerationSpec::new(dirs, &input, encoding, brotli, settings.min_savings)?.target;
+
+            let Some(output) = manifest.files.get(key).map(|e| tree.join(&e.output)) else {
+                verification.problems.push(Problem::Missing {
+                    encoding,
+                    identity: entry.path().to_owned(),
+                    expected,
+                });
+                continue;
+            };
+            accounted.insert(output.clone());
+
+            if !output.is_file() {
+                verification.problems.push(Problem::Missing {
+                    encoding,
+                    identity: entry.path().to_owned(),
+                    expected: output,
+                });
+                continue;
+            }
+
+            let mismatch = |reason: String| Problem::Mismatch {
+                encoding,
+                identity: entry.path().to_owned(),
+                output: output.clone(),
+                reason,
+            };
+
+            let mut decoded = Vec::new();
+            let result = std::fs::File::open(&output)
+                .and_then(|f| decoder(encoding, std::io::BufReader::new(f)))
+                .and_then(|mut d| d.read_to_end(&mut decoded));
+            verification.checked += 1;
+
+            if let Err(e) = result {
+                verification
+                    .problems
+                    .push(mismatch(format!("doesn't decode: {e}")));
+            } else if decoded != *data {
+                verification.problems.push(mismatch(format!(
+                    "decodes to {} bytes that differ from the {} bytes of the identity file",
+                    decoded.len(),
+                    data.len()
+                )));
+            } else if output != expected {
+                verification
+                    .problems
+                    .push(mismatch(format!("should be named {expected:?}")));
+            }
+        }
+
+        if !tree.exists() {
+            continue;
+        }
+        for old_entry in WalkDir::new(&tree) {
+            match old_entry {
+                Ok(e) if e.path().is_file() && !accounted.contains(e.path()) => {
+                    verification.problems.push(Problem::Orphan {
+                        encoding,
+                        output: e.path().to_owned(),
+                    })
+                }
+                Ok(_) => {}
+                Err(e) => verification.problems.push(Problem::Unreadable {
+                    path: e.path().unwrap_or(&tree).to_owned(),
+                    reason: e.to_string(),
+                }),
+            }
+        }
+    }
+
+    Ok(verification)
+}
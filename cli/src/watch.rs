@@ -0,0 +1,157 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{bail, Context as _};
+use notify_debouncer_full::{new_debouncer, notify::RecursiveMode, DebounceEventResult};
+use tokio::{
+    io::{AsyncReadExt as _, AsyncWriteExt as _},
+    net::TcpStream,
+    sync::mpsc,
+};
+use tracing::{error, info, warn};
+use trunk_compress::{Compressor, Report};
+
+/// How long a ping may take, so a server that never answers can't hold up the next run.
+const PING_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What to do besides compressing, once the identity directory settles down.
+#[derive(Debug, Clone)]
+pub struct WatchOptions {
+    /// how long the identity directory has to be quiet before compressing
+    pub debounce: Duration,
+    /// a file whose modification time is bumped after every change, e.g. for `cargo watch`
+    pub touch: Option<PathBuf>,
+    /// a local `http://` url to GET after every change
+    pub ping: Option<String>,
+}
+
+/// Compresses whenever something in the identity directory changes, until the process is
+/// killed. Every run is incremental, see [`Compressor::run`].
+pub async fn watch(compressor: &Compressor, options: &WatchOptions) -> anyhow::Result<()> {
+    let report = compressor.run().await?;
+    after_run(&report, options).await;
+
+    let identity_dir = compressor.identity_dir().canonicalize()?;
+    let stage_dir = identity_dir.join(".stage");
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let watched = identity_dir.clone();
+    let mut debouncer = new_debouncer(
+        options.debounce,
+        None,
+        move |result: DebounceEventResult| {
+            match result {
+                Ok(events) => {
+                    // trunk writes into `.stage` and renames it into place, only the latter counts
+                    let relevant = events
+                        .iter()
+                        .flat_map(|e| &e.paths)
+                        .any(|p| p.starts_with(&watched) && !p.starts_with(&stage_dir));
+                    if relevant {
+                        let _ = tx.send(());
+                    }
+                }
+                Err(errors) => {
+                    for e in errors {
+                        warn!("watch error: {e}");
+                    }
+                }
+            }
+        },
+    )?;
+    // the parent as well, trunk may replace the identity directory as a whole
+    let parent = identity_dir.parent().unwrap_or(&identity_dir);
+    debouncer
+        .watch(parent, RecursiveMode::Recursive)
+        .with_context(|| format!("failed to watch {parent:?}"))?;
+
+    info!("watching {:?} for changes", identity_dir);
+
+    while rx.recv().await.is_some() {
+        // whatever piled up during the last run is covered by the next one
+        while rx.try_recv().is_ok() {}
+
+        match compressor.run().await {
+            Ok(report) => after_run(&report, options).await,
+            Err(e) => error!("{e}"),
+        }
+    }
+
+    Ok(())
+}
+
+async fn after_run(report: &Report, options: &WatchOptions) {
+    for e in &report.errors {
+        error!("  {e}");
+    }
+
+    if !report.changed() {
+        return;
+    }
+
+    if let Some(path) = &options.touch {
+        match touch(path) {
+            Ok(()) => info!("touched {:?}", path),
+            Err(e) => error!("failed to touch {:?}: {e}", path),
+        }
+    }
+
+    if let Some(url) = &options.ping {
+        match tokio::time::timeout(PING_TIMEOUT, ping(url)).await {
+            Ok(Ok(status)) => info!("pinged {url}: {status}"),
+            Ok(Err(e)) => error!("failed to ping {url}: {e:#}"),
+            Err(_) => error!("failed to ping {url}: no response within {PING_TIMEOUT:?}"),
+        }
+    }
+}
+
+fn touch(path: &Path) -> std::io::Result<()> {
+    std::fs::File::options()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .set_modified(SystemTime::now())
+}
+
+/// A bare-bones `GET`, enough for a dev server on localhost. Returns the status line.
+async fn ping(url: &str) -> anyhow::Result<String> {
+    let Some(rest) = url.strip_prefix("http://") else {
+        bail!("only http:// urls are supported");
+    };
+    let (authority, path) = match rest.find('/') {
+        Some(i) => rest.split_at(i),
+        None => (rest, "/"),
+    };
+    let address = if authority.contains(':') {
+        authority.to_owned()
+    } else {
+        format!("{authority}:80")
+    };
+
+    let mut stream = TcpStream::connect(&address).await?;
+    stream
+        .write_all(
+            format!("GET {path} HTTP/1.1\r\nHost: {authority}\r\nConnection: close\r\n\r\n")
+                .as_bytes(),
+        )
+        .await?;
+
+    let mut response = Vec::new();
+    let mut buf = [0; 1024];
+    // the status line is all we care about, the body might be an endless event stream
+    while !response.contains(&b'\n') {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        response.extend_from_slice(&buf[..n]);
+    }
+
+    Ok(String::from_utf8_lossy(&response)
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .to_owned())
+}
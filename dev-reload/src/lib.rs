@@ -10,7 +10,7 @@ pub fn use_reload() {
     let version = use_mut_ref(|| None::<String>);
 
     use_effect(move || {
-        let mut es = EventSource::new(&format!("/version",)).unwrap();
+        let mut es = EventSource::new("/version").unwrap();
         let mut stream = es.subscribe("version").unwrap();
         yew::platform::spawn_local(async move {
             while let Some(Ok((_, msg))) = stream.next().await {
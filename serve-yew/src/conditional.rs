@@ -0,0 +1,214 @@
+//! Validators for embedded files and the conditional requests that check them.
+
+use std::{
+    fmt::Write as _,
+    time::{Duration, SystemTime},
+};
+
+use http::{header, HeaderMap, HeaderValue, Method};
+use rust_embed::EmbeddedFile;
+
+use crate::Encoding;
+
+/// The `ETag` and `Last-Modified` of one variant of an embedded file, from the hash and the
+/// modification time rust-embed records when embedding it.
+pub(crate) struct Validators {
+    pub(crate) etag: HeaderValue,
+    pub(crate) last_modified: Option<SystemTime>,
+}
+
+impl Validators {
+    /// `served` is the coding of the response, which differs from that of `file` when it's
+    /// decoded on the fly: the encoding is part of the tag, so every variant gets its own.
+    pub(crate) fn new(file: &EmbeddedFile, served: Encoding) -> Self {
+        let mut etag = String::with_capacity(64 + 16);
+        etag.push('"');
+        for byte in file.metadata.sha256_hash() {
+            write!(etag, "{byte:02x}").unwrap();
+        }
+        write!(etag, "-{}\"", served.to_str()).unwrap();
+
+        Self {
+            etag: HeaderValue::from_str(&etag).unwrap(),
+            last_modified: file
+                .metadata
+                .last_modified()
+                .map(|secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs)),
+        }
+    }
+
+    pub(crate) fn last_modified_header(&self) -> Option<HeaderValue> {
+        let date = httpdate::fmt_http_date(self.last_modified?);
+        HeaderValue::from_str(&date).ok()
+    }
+}
+
+/// The `If-None-Match` and `If-Modified-Since` of a `GET` or `HEAD` request.
+#[derive(Debug, Default)]
+pub(crate) struct Conditional {
+    /// `None` without the header
+    if_none_match: Option<Vec<String>>,
+    if_modified_since: Option<SystemTime>,
+}
+
+impl Conditional {
+    pub(crate) fn from_request(method: &Method, headers: &HeaderMap) -> Self {
+        if method != Method::GET && method != Method::HEAD {
+            return Self::default();
+        }
+
+        let mut if_none_match = headers.get_all(header::IF_NONE_MATCH).iter().peekable();
+        let if_none_match = if_none_match.peek().is_some().then(|| {
+            if_none_match
+                .filter_map(|v| v.to_str().ok())
+                .flat_map(|v| v.split(','))
+                .map(|tag| tag.trim().to_owned())
+                .collect()
+        });
+
+        let if_modified_since = headers
+            .get(header::IF_MODIFIED_SINCE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| httpdate::parse_http_date(v).ok());
+
+        Self {
+            if_none_match,
+            if_modified_since,
+        }
+    }
+
+    /// Whether the client's copy is still good, in which case it gets a `304 Not Modified`.
+    /// `If-Modified-Since` only counts without an `If-None-Match`, as RFC 9110 has it.
+    pub(crate) fn not_modified(&self, validators: &Validators) -> bool {
+        if let Some(tags) = &self.if_none_match {
+            // the weak comparison, `W/` doesn't matter
+            let etag = validators.etag.to_str().unwrap();
+            return tags
+                .iter()
+                .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag);
+        }
+
+        match (self.if_modified_since, validators.last_modified) {
+            (Some(since), Some(modified)) => modified <= since,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ETAG: &str = "\"c0ffee-br\"";
+
+    fn validators(last_modified: Option<u64>) -> Validators {
+        Validators {
+            etag: HeaderValue::from_static(ETAG),
+            last_modified: last_modified.map(|s| SystemTime::UNIX_EPOCH + Duration::from_secs(s)),
+        }
+    }
+
+    fn conditional(method: Method, headers: &[(header::HeaderName, &str)]) -> Conditional {
+        let mut map = HeaderMap::new();
+        for (name, value) in headers {
+            map.append(name, HeaderValue::from_str(value).unwrap());
+        }
+        Conditional::from_request(&method, &map)
+    }
+
+    fn get(headers: &[(header::HeaderName, &str)]) -> Conditional {
+        conditional(Method::GET, headers)
+    }
+
+    /// 2023-11-14T22:13:20Z
+    const MODIFIED: u64 = 1_700_000_000;
+
+    #[test]
+    fn unconditional() {
+        assert!(!get(&[]).not_modified(&validators(Some(MODIFIED))));
+    }
+
+    #[test]
+    fn if_none_match() {
+        let v = validators(None);
+        assert!(get(&[(header::IF_NONE_MATCH, ETAG)]).not_modified(&v));
+        assert!(!get(&[(header::IF_NONE_MATCH, "\"c0ffee-identity\"")]).not_modified(&v));
+        // not quoted, so not the same tag
+        assert!(!get(&[(header::IF_NONE_MATCH, "c0ffee-br")]).not_modified(&v));
+    }
+
+    #[test]
+    fn if_none_match_compares_weakly() {
+        let v = validators(None);
+        assert!(get(&[(header::IF_NONE_MATCH, "W/\"c0ffee-br\"")]).not_modified(&v));
+    }
+
+    #[test]
+    fn if_none_match_wildcard() {
+        assert!(get(&[(header::IF_NONE_MATCH, "*")]).not_modified(&validators(None)));
+    }
+
+    #[test]
+    fn if_none_match_lists() {
+        let v = validators(None);
+        let list = format!("\"a\",W/\"b\" ,  {ETAG}, \"c\"");
+        assert!(get(&[(header::IF_NONE_MATCH, &list)]).not_modified(&v));
+        assert!(!get(&[(header::IF_NONE_MATCH, "\"a\", \"b\"")]).not_modified(&v));
+        // spread over several headers
+        assert!(get(&[
+            (header::IF_NONE_MATCH, "\"a\""),
+            (header::IF_NONE_MATCH, ETAG)
+        ])
+        .not_modified(&v));
+    }
+
+    #[test]
+    fn if_modified_since() {
+        let v = validators(Some(MODIFIED));
+        let at = |secs| httpdate::fmt_http_date(SystemTime::UNIX_EPOCH + Duration::from_secs(secs));
+
+        assert!(get(&[(header::IF_MODIFIED_SINCE, &at(MODIFIED))]).not_modified(&v));
+        assert!(get(&[(header::IF_MODIFIED_SINCE, &at(MODIFIED + 60))]).not_modified(&v));
+        assert!(!get(&[(header::IF_MODIFIED_SINCE, &at(MODIFIED - 1))]).not_modified(&v));
+        assert!(!get(&[(header::IF_MODIFIED_SINCE, "yesterday")]).not_modified(&v));
+        // nothing to compare with
+        assert!(!get(&[(header::IF_MODIFIED_SINCE, &at(MODIFIED))]).not_modified(&validators(None)));
+    }
+
+    #[test]
+    fn if_modified_since_is_ignored_with_if_none_match() {
+        let v = validators(Some(MODIFIED));
+        let since = httpdate::fmt_http_date(SystemTime::UNIX_EPOCH + Duration::from_secs(MODIFIED));
+
+        assert!(!get(&[
+            (header::IF_NONE_MATCH, "\"other\""),
+            (header::IF_MODIFIED_SINCE, &since)
+        ])
+        .not_modified(&v));
+        assert!(get(&[
+            (header::IF_NONE_MATCH, ETAG),
+            (header::IF_MODIFIED_SINCE, "Thu, 01 Jan 1970 00:00:00 GMT")
+        ])
+        .not_modified(&v));
+    }
+
+    #[test]
+    fn only_get_and_head_are_conditional() {
+        let v = validators(Some(MODIFIED));
+        let headers = [(header::IF_NONE_MATCH, ETAG)];
+
+        assert!(conditional(Method::HEAD, &headers).not_modified(&v));
+        for method in [Method::POST, Method::PUT, Method::DELETE, Method::OPTIONS] {
+            assert!(!conditional(method, &headers).not_modified(&v));
+        }
+    }
+
+    #[test]
+    fn last_modified_header() {
+        assert_eq!(
+            validators(Some(MODIFIED)).last_modified_header().unwrap(),
+            "Tue, 14 Nov 2023 22:13:20 GMT"
+        );
+        assert_eq!(validators(None).last_modified_header(), None);
+    }
+}
@@ -0,0 +1,210 @@
+//! Compression and caching of rendered pages, which can't be compressed ahead of time like the
+//! embedded files.
+
+use std::{
+    collections::HashMap,
+    io,
+    sync::{Arc, Mutex},
+};
+
+use async_compression::{
+    tokio::write::{BrotliEncoder, GzipEncoder, ZstdEncoder},
+    Level,
+};
+use bytes::Bytes;
+use futures::{stream::BoxStream, StreamExt as _, TryStreamExt as _};
+use http::{HeaderMap, StatusCode};
+use tokio::io::{AsyncWrite, AsyncWriteExt as _};
+
+use crate::Encoding;
+
+/// A response body on its way to the client.
+pub type BodyStream = BoxStream<'static, io::Result<Bytes>>;
+
+/// Compresses rendered pages, see [`ServeYew::html_compressor`](crate::ServeYew::html_compressor).
+pub trait ResponseCompressor: Send + Sync + 'static {
+    /// What the compressor can encode into, the preferred ones first: they win when the client
+    /// accepts several equally.
+    fn encodings(&self) -> Vec<Encoding>;
+
+    /// Encodes `body` as it arrives. Called with one of [`ResponseCompressor::encodings`] only.
+    fn compress(&self, encoding: Encoding, body: BodyStream) -> BodyStream;
+}
+
+/// The default [`ResponseCompressor`]: levels that are fast enough to compress every response,
+/// flushing after every chunk of the body so nothing is held back. `None` turns an encoding
+/// off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamCompressor {
+    /// 0 to 11
+    pub brotli: Option<u32>,
+    /// 0 to 9
+    pub gzip: Option<u32>,
+    /// 1 to 22
+    pub zstd: Option<i32>,
+}
+
+impl Default for StreamCompressor {
+    fn default() -> Self {
+        Self {
+            brotli: Some(4),
+            gzip: Some(6),
+            zstd: Some(3),
+        }
+    }
+}
+
+impl ResponseCompressor for StreamCompressor {
+    fn encodings(&self) -> Vec<Encoding> {
+        [
+            self.brotli.map(|_| Encoding::Brotli),
+            self.zstd.map(|_| Encoding::Zstd),
+            self.gzip.map(|_| Encoding::Gzip),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+
+    fn compress(&self, encoding: Encoding, body: BodyStream) -> BodyStream {
+        // only called with encodings that are turned on
+        let level = |level: Option<u32>| Level::Precise(level.unwrap_or_default() as i32);
+
+        match encoding {
+            Encoding::Brotli => encode(
+                BrotliEncoder::with_quality(Vec::new(), level(self.brotli)),
+                BrotliEncoder::get_mut,
+                body,
+            ),
+            Encoding::Gzip => encode(
+                GzipEncoder::with_quality(Vec::new(), level(self.gzip)),
+                GzipEncoder::get_mut,
+                body,
+            ),
+            Encoding::Zstd => encode(
+                ZstdEncoder::with_quality(
+                    Vec::new(),
+                    Level::Precise(self.zstd.unwrap_or_default()),
+                ),
+                ZstdEncoder::get_mut,
+                body,
+            ),
+            Encoding::Identity => body,
+        }
+    }
+}
+
+/// Runs `body` through `encoder`, yielding whatever it produced after every chunk.
+fn encode<E>(encoder: E, output: fn(&mut E) -> &mut Vec<u8>, body: BodyStream) -> BodyStream
+where
+    E: AsyncWrite + Unpin + Send + 'static,
+{
+    futures::stream::unfold(Some((encoder, body)), move |state| async move {
+        let (mut encoder, mut body) = state?;
+        loop {
+            match body.next().await {
+                Some(Ok(chunk)) => {
+                    let written = async {
+                        encoder.write_all(&chunk).await?;
+                        encoder.flush().await
+                    };
+                    if let Err(e) = written.await {
+                        return Some((Err(e), None));
+                    }
+                    let out = std::mem::take(output(&mut encoder));
+                    if !out.is_empty() {
+                        return Some((Ok(Bytes::from(out)), Some((encoder, body))));
+                    }
+                }
+                Some(Err(e)) => return Some((Err(e), None)),
+                None => {
+                    let out = encoder
+                        .shutdown()
+                        .await
+                        .map(|()| Bytes::from(std::mem::take(output(&mut encoder))));
+                    return Some((out, None));
+                }
+            }
+        }
+    })
+    .boxed()
+}
+
+/// The most recently used renders, compressed, see [`Process::cache_key`](crate::Process::cache_key).
+pub(crate) struct RenderCache {
+    capacity: usize,
+    entries: Mutex<Entries>,
+}
+
+#[derive(Default)]
+struct Entries {
+    /// the page, and when it was last used
+    map: HashMap<(String, Encoding), (CachedRender, u64)>,
+    clock: u64,
+}
+
+/// A [`Rendered`](crate::Rendered) page without its cookies, which are taken from each request.
+#[derive(Clone)]
+pub(crate) struct CachedRender {
+    pub(crate) status: StatusCode,
+    pub(crate) headers: HeaderMap,
+    pub(crate) body: Bytes,
+}
+
+impl RenderCache {
+    pub(crate) fn new(capacity: usize) -> Arc<Self> {
+        Arc::new(Self {
+            capacity,
+            entries: Mutex::default(),
+        })
+    }
+
+    pub(crate) fn get(&self, key: &str, encoding: Encoding) -> Option<CachedRender> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.clock += 1;
+        let now = entries.clock;
+
+        let (render, used) = entries.map.get_mut(&(key.to_owned(), encoding))?;
+        *used = now;
+        Some(render.clone())
+    }
+
+    /// Buffers `body` to keep it, evicting the least recently used render if the cache is full.
+    pub(crate) async fn insert(
+        &self,
+        key: String,
+        encoding: Encoding,
+        status: StatusCode,
+        headers: HeaderMap,
+        body: BodyStream,
+    ) -> io::Result<Bytes> {
+        let chunks: Vec<Bytes> = body.try_collect().await?;
+        let bytes = Bytes::from(chunks.concat());
+        let render = CachedRender {
+            status,
+            headers,
+            body: bytes.clone(),
+        };
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.clock += 1;
+        let now = entries.clock;
+
+        if entries.map.len() >= self.capacity && !entries.map.contains_key(&(key.clone(), encoding))
+        {
+            let oldest = entries
+                .map
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(k, _)| k.clone());
+            if let Some(oldest) = oldest {
+                entries.map.remove(&oldest);
+            }
+        }
+        if self.capacity > 0 {
+            entries.map.insert((key, encoding), (render, now));
+        }
+
+        Ok(bytes)
+    }
+}
@@ -23,11 +23,24 @@ use futures::{FutureExt, Stream, StreamExt as _};
 
 use bytes::Bytes;
 use derive_more::{Deref, DerefMut, From};
-use futures::future::BoxFuture;
-use http::{header, HeaderName, HeaderValue, Response, StatusCode};
+use futures::{future::BoxFuture, stream::BoxStream};
+use http::{header, HeaderMap, HeaderName, HeaderValue, Response, StatusCode};
 use rust_embed::{EmbeddedFile, Filenames};
 use tower_service::Service;
 
+mod conditional;
+#[cfg(feature = "compression")]
+mod html;
+mod static_paths;
+
+use conditional::{Conditional, Validators};
+
+#[cfg(feature = "compression")]
+use html::RenderCache;
+#[cfg(feature = "compression")]
+pub use html::{BodyStream, ResponseCompressor, StreamCompressor};
+pub use static_paths::StaticPaths;
+
 #[macro_export]
 macro_rules! identity {
     ($name:ident) => {
@@ -46,6 +59,7 @@ macro_rules! brotli_code {
         #[derive(Copy, Clone, ::rust_embed::RustEmbed)]
         #[folder = "../frontend/dist/brotli/"]
         #[exclude = "assets/*"]
+        #[exclude = "manifest.json"]
         pub struct $name;
     };
 }
@@ -60,6 +74,15 @@ macro_rules! brotli_assets {
     };
 }
 
+/// The mapping `ServeYew::new` takes in compression mode, read from the manifest
+/// `trunk-compress` writes next to the brotli files.
+#[macro_export]
+macro_rules! brotli_asset_mapping {
+    () => {
+        $crate::asset_mapping(include_str!("../../frontend/dist/brotli/manifest.json"))
+    };
+}
+
 #[macro_export]
 macro_rules! index {
     ($name:ident) => {
@@ -68,6 +91,34 @@ macro_rules! index {
     };
 }
 
+#[cfg(feature = "compression")]
+#[derive(serde::Deserialize)]
+struct Manifest<'a> {
+    #[serde(borrow)]
+    files: BTreeMap<&'a str, ManifestEntry<'a>>,
+}
+
+#[cfg(feature = "compression")]
+#[derive(serde::Deserialize)]
+struct ManifestEntry<'a> {
+    output: &'a str,
+}
+
+/// Turns a `manifest.json` of the brotli tree into `assets/logo.svg` ->
+/// `logo-<hash>.svg.br` entries, the latter relative to the brotli assets folder.
+#[cfg(feature = "compression")]
+pub fn asset_mapping(manifest: &'static str) -> BTreeMap<&'static str, &'static str> {
+    let manifest: Manifest<'static> =
+        serde_json::from_str(manifest).expect("invalid trunk-compress manifest");
+
+    manifest
+        .files
+        .into_iter()
+        .filter(|(path, _)| path.starts_with("assets/"))
+        .filter_map(|(path, entry)| Some((path, entry.output.strip_prefix("assets/")?)))
+        .collect()
+}
+
 #[derive(Clone)]
 pub struct NoAssets;
 
@@ -145,6 +196,99 @@ pub trait Process {
         extracted_headers: HashMap<HeaderName, HeaderValue>,
         cookies: Self::Cookies,
     ) -> impl Future<Output = (String, Self::Cookies)> + Send;
+
+    /// Like [`Process::render`], but the page is sent as it's rendered, e.g. from yew's
+    /// `ServerRenderer::render_stream`: every chunk is compressed and flushed to the client right
+    /// away, so the `<head>` with its preload links can go out before the body is done. The
+    /// cookies are written to the headers, so they have to be final once the future resolves.
+    ///
+    /// Defaults to sending what [`Process::render`] returns in one chunk.
+    fn render_stream(
+        &self,
+        data: Cow<'static, [u8]>,
+        path: String,
+        queries: HashMap<String, String>,
+        app_state: &Self::State,
+        extracted_headers: HashMap<HeaderName, HeaderValue>,
+        cookies: Self::Cookies,
+    ) -> impl Future<Output = (impl Stream<Item = Bytes> + Send + 'static, Self::Cookies)> + Send
+    {
+        self.render(data, path, queries, app_state, extracted_headers, cookies)
+            .map(|(html, cookies)| (futures::stream::once(async { Bytes::from(html) }), cookies))
+    }
+
+    /// Like [`Process::render_stream`], with control over the status code and the headers, e.g.
+    /// a `404` for unknown routes, redirects, or a `Content-Language`.
+    ///
+    /// Defaults to a `200` with what [`Process::render_stream`] returns.
+    fn render_response(
+        &self,
+        data: Cow<'static, [u8]>,
+        path: String,
+        queries: HashMap<String, String>,
+        app_state: &Self::State,
+        extracted_headers: HashMap<HeaderName, HeaderValue>,
+        cookies: Self::Cookies,
+    ) -> impl Future<Output = Rendered<Self::Cookies>> + Send {
+        self.render_stream(data, path, queries, app_state, extracted_headers, cookies)
+            .map(|(body, cookies)| Rendered::stream(body, cookies))
+    }
+
+    /// A key for pages that are the same for every user. With `ServeYew::cache_renders`, such
+    /// a page is rendered and compressed once per key and encoding, then served from the cache
+    /// with the cookies from [`Process::get_cookies`] until it's evicted. `None`, the default,
+    /// renders every time.
+    ///
+    /// Without the `compression` feature there is no cache and this is never called.
+    #[allow(unused_variables)]
+    fn cache_key(
+        &self,
+        path: &str,
+        queries: &HashMap<String, String>,
+        app_state: &Self::State,
+        extracted_headers: &HashMap<HeaderName, HeaderValue>,
+    ) -> Option<String> {
+        None
+    }
+}
+
+/// A rendered page, see [`Process::render_response`].
+///
+/// The headers are sent as given, except for `Content-Encoding`, which is up to `ServeYew`.
+/// `Content-Type` defaults to `text/html` and `Cache-Control` to revalidating every time.
+pub struct Rendered<C> {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: BoxStream<'static, Bytes>,
+    pub cookies: C,
+}
+
+impl<C> Rendered<C> {
+    /// A `200 OK` page, sent in one chunk.
+    pub fn new(html: String, cookies: C) -> Self {
+        Self::stream(futures::stream::once(async { Bytes::from(html) }), cookies)
+    }
+
+    /// A `200 OK` page, sent as it's rendered.
+    pub fn stream(body: impl Stream<Item = Bytes> + Send + 'static, cookies: C) -> Self {
+        Self {
+            status: StatusCode::OK,
+            headers: HeaderMap::new(),
+            body: body.boxed(),
+            cookies,
+        }
+    }
+
+    pub fn status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Adds a header, keeping the values already set for `name`.
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.append(name, value);
+        self
+    }
 }
 
 #[derive(Clone)]
@@ -165,6 +309,11 @@ pub struct ServeYew<
     brotli_asset_mapping: BTreeMap<&'static str, &'static str>,
     #[cfg(feature = "compression")]
     index: &'static [u8],
+    #[cfg(feature = "compression")]
+    html_compressor: Arc<dyn ResponseCompressor>,
+    #[cfg(feature = "compression")]
+    render_cache: Option<Arc<RenderCache>>,
+    static_paths: Arc<StaticPaths>,
     g: G,
     app_state: S,
     headers: HashSet<HeaderName>,
@@ -180,7 +329,7 @@ impl<A: RustEmbed + Clone + Send, G: Process<State = S> + Clone + Send, S: Clone
             .find(|f| f.ends_with(".js"))
             .map(|f| {
                 f.split('-')
-                    .last()
+                    .next_back()
                     .unwrap()
                     .trim_end_matches(".js")
                     .to_owned()
@@ -192,26 +341,35 @@ impl<A: RustEmbed + Clone + Send, G: Process<State = S> + Clone + Send, S: Clone
         Self {
             _phantom: PhantomData,
             mime_map: Arc::new(MimeMap::init(A::iter())),
+            static_paths: Arc::new(StaticPaths::default()),
             g,
             app_state,
             headers,
         }
     }
+
+    /// Which missing paths get a `404` instead of a rendered page, [`StaticPaths::default`]
+    /// unless set.
+    pub fn static_paths(mut self, static_paths: StaticPaths) -> Self {
+        self.static_paths = Arc::new(static_paths);
+        self
+    }
 }
 
 #[cfg(not(feature = "compression"))]
 impl<A: RustEmbed + Clone + Send, G: Process<State = S> + Clone + Send, S: Clone + Send>
     ServeYew<A, G, S>
 {
-    fn get_asset(&self, path: &str) -> Option<(Bytes, HeaderValue, Encoding)> {
+    fn get_asset(&self, path: &str) -> Option<(Bytes, HeaderValue, Encoding, Validators)> {
         let file = Self::get_fr(path);
 
         file.map(|(f, e)| {
             let mime = self.mime_map.get(path).unwrap();
 
+            let validators = Validators::new(&f, e);
             let data = f.data.into_owned();
 
-            (Bytes::from(data), mime.to_owned(), e)
+            (Bytes::from(data), mime.to_owned(), e, validators)
         })
     }
 
@@ -240,7 +398,7 @@ impl<
             .find(|f| f.ends_with(".js.br"))
             .map(|f| {
                 f.split('-')
-                    .last()
+                    .next_back()
                     .unwrap()
                     .trim_end_matches(".js.br")
                     .to_string()
@@ -248,31 +406,98 @@ impl<
             .unwrap()
     }
 
-    fn get_asset(&self, path: &str) -> Option<(Bytes, HeaderValue, Encoding)> {
-        let file = self.get_fr(path);
-
-        file.map(|(f, e)| {
-            let mime = self.mime_map.get(path).unwrap();
+    fn get_asset(
+        &self,
+        path: &str,
+        accept: &AcceptEncoding,
+    ) -> Option<Result<(Variant, HeaderValue), NotAcceptable>> {
+        let file = self.get_fr(path, accept);
 
-            let data = f.data.into_owned();
+        file.map(|f| {
+            f.map(|v| {
+                let mime = self.mime_map.get(path).unwrap();
 
-            (Bytes::from(data), mime.to_owned(), e)
+                (v, mime.to_owned())
+            })
         })
     }
 
-    fn get_fr(&self, path: &str) -> Option<(EmbeddedFile, Encoding)> {
-        if path.starts_with("assets/") {
-            // todo: automate this with a macro
-            if let Some(&file) = self.brotli_asset_mapping.get(path) {
-                return C1::get(file).map(|f| (f, Encoding::Brotli));
-            }
+    /// Picks the variant of `path` the client prefers. Identity is always on offer as long as
+    /// any variant exists: code files are only embedded as brotli, so they get decoded on the fly,
+    /// and assets `trunk-compress` didn't find worth compressing only exist as identity.
+    fn get_fr(
+        &self,
+        path: &str,
+        accept: &AcceptEncoding,
+    ) -> Option<Result<Variant, NotAcceptable>> {
+        let brotli = self.get_variant(path, Encoding::Brotli);
+        let identity = self.get_variant(path, Encoding::Identity);
+
+        if brotli.is_none() && identity.is_none() {
+            return None;
+        }
 
-            return A::get(path).map(|f| (f, Encoding::Identity));
+        let available: &[Encoding] = if brotli.is_some() {
+            &[Encoding::Brotli, Encoding::Identity]
+        } else {
+            &[Encoding::Identity]
+        };
+
+        let Some(encoding) = accept.negotiate(available) else {
+            return Some(Err(NotAcceptable));
+        };
+
+        Some(Ok(match (encoding, identity, brotli) {
+            (Encoding::Identity, Some(f), _) => Variant::Embedded(f, Encoding::Identity),
+            (Encoding::Identity, None, Some(f)) => Variant::DecodeBrotli(f),
+            (_, _, Some(f)) => Variant::Embedded(f, Encoding::Brotli),
+            _ => unreachable!("negotiated an encoding that is not available"),
+        }))
+    }
+
+    fn get_variant(&self, path: &str, encoding: Encoding) -> Option<EmbeddedFile> {
+        match encoding {
+            Encoding::Brotli if path.starts_with("assets/") => self
+                .brotli_asset_mapping
+                .get(path)
+                .and_then(|&file| C1::get(file)),
+            Encoding::Brotli => C::get(&format!("{}.br", path)),
+            Encoding::Identity => A::get(path),
+            // no gzip or zstd tree is embedded (yet), they're only used for rendered html
+            Encoding::Gzip | Encoding::Zstd => None,
         }
+    }
+}
 
-        let path = format!("{}.br", path);
+/// An embedded file picked by negotiation, possibly still to be decoded.
+#[cfg(feature = "compression")]
+enum Variant {
+    Embedded(EmbeddedFile, Encoding),
+    DecodeBrotli(EmbeddedFile),
+}
 
-        C::get(&path).map(|f| (f, Encoding::Brotli))
+#[cfg(feature = "compression")]
+impl Variant {
+    fn validators(&self) -> Validators {
+        match self {
+            Variant::Embedded(f, e) => Validators::new(f, *e),
+            Variant::DecodeBrotli(f) => Validators::new(f, Encoding::Identity),
+        }
+    }
+
+    async fn into_bytes(self) -> (Bytes, Encoding) {
+        use {async_compression::tokio::write::BrotliDecoder, tokio::io::AsyncWriteExt};
+
+        match self {
+            Variant::Embedded(f, e) => (Bytes::from(f.data.into_owned()), e),
+            Variant::DecodeBrotli(f) => {
+                let mut buf = Vec::new();
+                let mut decoder = BrotliDecoder::new(&mut buf);
+                decoder.write_all(&f.data).await.unwrap();
+                decoder.shutdown().await.unwrap();
+                (Bytes::from(buf), Encoding::Identity)
+            }
+        }
     }
 }
 
@@ -299,11 +524,35 @@ impl<
             mime_map: Arc::new(MimeMap::init(A::iter(), C::iter())),
             brotli_asset_mapping,
             index,
+            html_compressor: Arc::new(StreamCompressor::default()),
+            render_cache: None,
+            static_paths: Arc::new(StaticPaths::default()),
             g,
             app_state,
             headers,
         }
     }
+
+    /// How rendered pages get compressed, [`StreamCompressor::default`] unless set.
+    pub fn html_compressor(mut self, compressor: impl ResponseCompressor) -> Self {
+        self.html_compressor = Arc::new(compressor);
+        self
+    }
+
+    /// Keeps up to `capacity` compressed renders of the pages [`Process::cache_key`] gives a
+    /// key, dropping the least recently used ones. Such pages are compressed in full before
+    /// they are sent, so when the compressor fails, they get a `500` instead.
+    pub fn cache_renders(mut self, capacity: usize) -> Self {
+        self.render_cache = Some(RenderCache::new(capacity));
+        self
+    }
+
+    /// Which missing paths get a `404` instead of a rendered page, [`StaticPaths::default`]
+    /// unless set.
+    pub fn static_paths(mut self, static_paths: StaticPaths) -> Self {
+        self.static_paths = Arc::new(static_paths);
+        self
+    }
 }
 
 #[cfg(feature = "compression")]
@@ -340,15 +589,56 @@ impl<
             }
 
             let extracted_headers = extracted_headers(&req, &s.headers);
+            let accept = AcceptEncoding::from_headers(req.headers());
+            let conditional = Conditional::from_request(req.method(), req.headers());
             let out = s.g.get_cookies(req, &s.app_state).await;
 
-            let (bytes, mime, encoding, cookie_jars) = match s.get_asset(&uri.path()[1..]) {
-                Some(asset) => (asset.0, asset.1, asset.2, out),
+            let asset = s.get_asset(&uri.path()[1..], &accept);
+            match asset {
+                Some(Ok((variant, mime))) => {
+                    let validators = variant.validators();
+                    if conditional.not_modified(&validators) {
+                        // no need to decode anything
+                        return TheOutput::NotModified { validators, mime };
+                    }
+                    let (bytes, encoding) = variant.into_bytes().await;
+                    TheOutput::asset(Body::from(bytes), mime, encoding, validators, out)
+                }
+                Some(Err(NotAcceptable)) => TheOutput::NotAcceptable,
+                None if s.static_paths.matches(&uri.path()[1..]) => TheOutput::NotFound,
                 None => {
                     let queries = Query::<HashMap<String, String>>::try_from_uri(&uri).unwrap();
 
-                    let (rendered, cookie_jars) =
-                        s.g.render(
+                    let mut available = s.html_compressor.encodings();
+                    available.push(Encoding::Identity);
+                    let Some(encoding) = accept.negotiate(&available) else {
+                        return TheOutput::NotAcceptable;
+                    };
+                    let html = HeaderValue::from_static("text/html");
+
+                    let cache = s.render_cache.as_ref().and_then(|cache| {
+                        let key = s.g.cache_key(
+                            uri.path(),
+                            &queries.0,
+                            &s.app_state,
+                            &extracted_headers,
+                        )?;
+                        Some((cache, key))
+                    });
+                    if let Some(cached) = cache.as_ref().and_then(|(c, key)| c.get(key, encoding)) {
+                        return TheOutput::Other {
+                            status: cached.status,
+                            headers: cached.headers,
+                            body: Body::from(cached.body),
+                            mime: html,
+                            encoding,
+                            validators: None,
+                            cookie_jars: out,
+                        };
+                    }
+
+                    let rendered =
+                        s.g.render_response(
                             s.index.into(),
                             uri.path().to_owned(),
                             queries.0,
@@ -358,30 +648,51 @@ impl<
                         )
                         .await;
 
-                    use {async_compression::tokio::write::GzipEncoder, tokio::io::AsyncWriteExt};
-
-                    let rendered = {
-                        let mut buf = Vec::new();
-                        let mut encoder = GzipEncoder::new(&mut buf);
-                        encoder.write_all(rendered.as_bytes()).await.unwrap();
-                        encoder.shutdown().await.unwrap();
-                        buf
+                    let compressed = s
+                        .html_compressor
+                        .compress(encoding, rendered.body.map(Ok).boxed());
+
+                    let body = match cache {
+                        Some((cache, key)) => {
+                            let inserted = cache
+                                .insert(
+                                    key,
+                                    encoding,
+                                    rendered.status,
+                                    rendered.headers.clone(),
+                                    compressed,
+                                )
+                                .await;
+                            match inserted {
+                                Ok(bytes) => Body::from(bytes),
+                                // a custom compressor may fail, nothing is cached then
+                                Err(e) => {
+                                    tracing::error!("failed to compress {}: {e}", uri.path());
+                                    return TheOutput::Other {
+                                        status: StatusCode::INTERNAL_SERVER_ERROR,
+                                        headers: HeaderMap::new(),
+                                        body: Body::empty(),
+                                        mime: html,
+                                        encoding: Encoding::Identity,
+                                        validators: None,
+                                        cookie_jars: rendered.cookies,
+                                    };
+                                }
+                            }
+                        }
+                        None => Body::from_stream(compressed),
                     };
 
-                    (
-                        Bytes::from(rendered),
-                        HeaderValue::from_static("text/html"),
-                        Encoding::Gzip,
-                        cookie_jars,
-                    )
+                    TheOutput::Other {
+                        status: rendered.status,
+                        headers: rendered.headers,
+                        body,
+                        mime: html,
+                        encoding,
+                        validators: None,
+                        cookie_jars: rendered.cookies,
+                    }
                 }
-            };
-
-            TheOutput::Other {
-                bytes,
-                mime,
-                encoding,
-                cookie_jars,
             }
         }
         .then(|o| ResponseFuture {
@@ -446,18 +757,25 @@ impl<
             let uri = req.uri().clone();
 
             let extracted_headers = extracted_headers(&req, &s.headers);
+            let conditional = Conditional::from_request(req.method(), req.headers());
 
             let out = s.g.get_cookies(req, &s.app_state).await;
 
-            let (bytes, mime, encoding, cookie_jars) = match s.get_asset(&uri.path()[1..]) {
-                Some(asset) => (asset.0, asset.1, asset.2, out),
+            match s.get_asset(&uri.path()[1..]) {
+                Some((_, mime, _, validators)) if conditional.not_modified(&validators) => {
+                    TheOutput::NotModified { validators, mime }
+                }
+                Some((bytes, mime, encoding, validators)) => {
+                    TheOutput::asset(Body::from(bytes), mime, encoding, validators, out)
+                }
+                None if s.static_paths.matches(&uri.path()[1..]) => TheOutput::NotFound,
                 None => {
                     let queries = Query::<HashMap<String, String>>::try_from_uri(&uri).unwrap();
 
                     let data: Cow<'static, [u8]> = A::get("index.html").unwrap().data;
 
-                    let (rendered, cookie_jars) =
-                        s.g.render(
+                    let rendered =
+                        s.g.render_response(
                             data,
                             uri.path().to_owned(),
                             queries.0,
@@ -467,20 +785,16 @@ impl<
                         )
                         .await;
 
-                    (
-                        Bytes::from(rendered),
-                        HeaderValue::from_static("text/html"),
-                        Encoding::Identity,
-                        cookie_jars,
-                    )
+                    TheOutput::Other {
+                        status: rendered.status,
+                        headers: rendered.headers,
+                        body: Body::from_stream(rendered.body.map(Ok::<_, Infallible>)),
+                        mime: HeaderValue::from_static("text/html"),
+                        encoding: Encoding::Identity,
+                        validators: None,
+                        cookie_jars: rendered.cookies,
+                    }
                 }
-            };
-
-            TheOutput::Other {
-                bytes,
-                mime,
-                encoding,
-                cookie_jars,
             }
         }
         .then(|f| ResponseFuture {
@@ -490,12 +804,15 @@ impl<
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
-enum Encoding {
+/// A content coding, as in `Accept-Encoding` and `Content-Encoding`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Encoding {
     #[cfg(feature = "compression")]
     Gzip,
     #[cfg(feature = "compression")]
     Brotli,
+    #[cfg(feature = "compression")]
+    Zstd,
     Identity,
 }
 
@@ -506,6 +823,8 @@ impl Encoding {
             Encoding::Gzip => "gzip",
             #[cfg(feature = "compression")]
             Encoding::Brotli => "br",
+            #[cfg(feature = "compression")]
+            Encoding::Zstd => "zstd",
             Encoding::Identity => "identity",
         }
     }
@@ -515,16 +834,229 @@ impl Encoding {
     }
 }
 
+/// The client asked for codings we don't have, answered with `406 Not Acceptable`.
+#[cfg(feature = "compression")]
+struct NotAcceptable;
+
+/// The content codings a client accepts, parsed from its `Accept-Encoding` headers.
+#[cfg(feature = "compression")]
+#[derive(Debug)]
+struct AcceptEncoding {
+    /// `None` when the request has no `Accept-Encoding` at all, meaning anything goes
+    codings: Option<Vec<(String, f32)>>,
+}
+
+#[cfg(feature = "compression")]
+impl AcceptEncoding {
+    fn from_headers(headers: &http::HeaderMap) -> Self {
+        let mut values = headers.get_all(header::ACCEPT_ENCODING).iter().peekable();
+
+        if values.peek().is_none() {
+            return Self { codings: None };
+        }
+
+        let codings = values
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .filter_map(|item| {
+                let mut params = item.split(';');
+                let coding = params.next()?.trim().to_ascii_lowercase();
+                if coding.is_empty() {
+                    return None;
+                }
+
+                let q = params
+                    .filter_map(|p| p.trim().strip_prefix("q=").or(p.trim().strip_prefix("Q=")))
+                    .find_map(|q| q.trim().parse::<f32>().ok())
+                    .unwrap_or(1.0)
+                    .clamp(0.0, 1.0);
+
+                let coding = match coding.as_str() {
+                    "x-gzip" => "gzip".to_owned(),
+                    _ => coding,
+                };
+
+                Some((coding, q))
+            })
+            .collect();
+
+        Self {
+            codings: Some(codings),
+        }
+    }
+
+    fn quality(&self, encoding: Encoding) -> f32 {
+        let Some(codings) = &self.codings else {
+            return 1.0;
+        };
+
+        let lookup = |name: &str| codings.iter().find(|(c, _)| c == name).map(|(_, q)| *q);
+
+        lookup(encoding.to_str())
+            .or_else(|| lookup("*"))
+            // identity is acceptable unless it's ruled out explicitly
+            .unwrap_or(if encoding == Encoding::Identity {
+                1.0
+            } else {
+                0.0
+            })
+    }
+
+    /// The acceptable encoding with the highest q-value, ties going to whichever comes first
+    /// in `available`.
+    fn negotiate(&self, available: &[Encoding]) -> Option<Encoding> {
+        let mut best: Option<(Encoding, f32)> = None;
+
+        for &encoding in available {
+            let q = self.quality(encoding);
+            if q > 0.0 && best.is_none_or(|(_, best_q)| q > best_q) {
+                best = Some((encoding, q));
+            }
+        }
+
+        best.map(|(e, _)| e)
+    }
+}
+
+#[cfg(all(test, feature = "compression"))]
+mod accept_encoding_tests {
+    use super::*;
+
+    use Encoding::{Brotli, Gzip, Identity, Zstd};
+
+    fn parse(values: &[&str]) -> AcceptEncoding {
+        let mut headers = http::HeaderMap::new();
+        for value in values {
+            headers.append(
+                header::ACCEPT_ENCODING,
+                HeaderValue::from_str(value).unwrap(),
+            );
+        }
+        AcceptEncoding::from_headers(&headers)
+    }
+
+    #[test]
+    fn missing_header_accepts_anything() {
+        let accept = parse(&[]);
+        assert_eq!(accept.quality(Brotli), 1.0);
+        assert_eq!(accept.quality(Identity), 1.0);
+        assert_eq!(accept.negotiate(&[Brotli, Identity]), Some(Brotli));
+    }
+
+    #[test]
+    fn empty_header_accepts_identity_only() {
+        let accept = parse(&[""]);
+        assert_eq!(accept.quality(Brotli), 0.0);
+        assert_eq!(accept.quality(Identity), 1.0);
+        assert_eq!(accept.negotiate(&[Brotli, Gzip, Identity]), Some(Identity));
+    }
+
+    #[test]
+    fn zero_q_rules_out_a_coding_the_wildcard_allows() {
+        let accept = parse(&["br;q=0, *"]);
+        assert_eq!(accept.quality(Brotli), 0.0);
+        assert_eq!(accept.quality(Gzip), 1.0);
+        assert_eq!(accept.negotiate(&[Brotli, Identity]), Some(Identity));
+        assert_eq!(accept.negotiate(&[Brotli, Gzip, Identity]), Some(Gzip));
+        assert_eq!(accept.negotiate(&[Brotli]), None);
+    }
+
+    #[test]
+    fn identity_can_be_ruled_out() {
+        let accept = parse(&["identity;q=0"]);
+        assert_eq!(accept.quality(Identity), 0.0);
+        assert_eq!(accept.negotiate(&[Identity]), None);
+        assert_eq!(accept.negotiate(&[Brotli, Identity]), None);
+
+        assert_eq!(
+            parse(&["gzip, identity;q=0"]).negotiate(&[Brotli, Gzip, Identity]),
+            Some(Gzip)
+        );
+    }
+
+    #[test]
+    fn zero_q_wildcard_rules_out_everything_unlisted() {
+        let accept = parse(&["*;q=0"]);
+        assert_eq!(accept.quality(Identity), 0.0);
+        assert_eq!(accept.negotiate(&[Brotli, Gzip, Identity]), None);
+
+        assert_eq!(
+            parse(&["gzip, *;q=0"]).negotiate(&[Brotli, Gzip, Identity]),
+            Some(Gzip)
+        );
+    }
+
+    #[test]
+    fn ties_go_to_the_first_available() {
+        let accept = parse(&["gzip, br, zstd"]);
+        assert_eq!(accept.negotiate(&[Brotli, Zstd, Gzip]), Some(Brotli));
+        assert_eq!(accept.negotiate(&[Gzip, Brotli]), Some(Gzip));
+        assert_eq!(accept.negotiate(&[Zstd, Identity]), Some(Zstd));
+    }
+
+    #[test]
+    fn higher_q_wins_over_order() {
+        let accept = parse(&["br;q=0.5, gzip;q=0.8"]);
+        assert_eq!(accept.negotiate(&[Brotli, Gzip, Identity]), Some(Identity));
+        assert_eq!(accept.negotiate(&[Brotli, Gzip]), Some(Gzip));
+    }
+
+    #[test]
+    fn parsing_is_lenient() {
+        // several headers, odd spacing and case, x-gzip, a q-value out of range
+        let accept = parse(&["GZIP ; Q=0.3", " br;q=2 ,, x-gzip;q=0.9"]);
+        assert_eq!(accept.quality(Brotli), 1.0);
+        assert_eq!(accept.quality(Gzip), 0.3);
+        assert_eq!(accept.negotiate(&[Gzip, Brotli]), Some(Brotli));
+    }
+}
+
 enum TheOutput<C> {
     Version(String),
+    #[cfg(feature = "compression")]
+    NotAcceptable,
+    /// a [`StaticPaths`] path without an embedded file
+    NotFound,
+    /// an embedded file the client has already
+    NotModified {
+        validators: Validators,
+        mime: HeaderValue,
+    },
     Other {
-        bytes: Bytes,
+        status: StatusCode,
+        /// from [`Rendered::headers`]
+        headers: HeaderMap,
+        body: Body,
         mime: HeaderValue,
         encoding: Encoding,
+        /// `None` for rendered pages
+        validators: Option<Validators>,
         cookie_jars: C,
     },
 }
 
+impl<C> TheOutput<C> {
+    fn asset(
+        body: Body,
+        mime: HeaderValue,
+        encoding: Encoding,
+        validators: Validators,
+        cookie_jars: C,
+    ) -> Self {
+        TheOutput::Other {
+            status: StatusCode::OK,
+            headers: HeaderMap::new(),
+            body,
+            mime,
+            encoding,
+            validators: Some(validators),
+            cookie_jars,
+        }
+    }
+}
+
+// only ever inside a boxed future
+#[allow(clippy::large_enum_variant)]
 enum Inner<C> {
     Valid(TheOutput<C>),
     Invalid,
@@ -534,48 +1066,110 @@ pub struct ResponseFuture<C> {
     inner: Inner<C>,
 }
 
+// nothing is pinned, the output is moved out once
+impl<C> Unpin for ResponseFuture<C> {}
+
 pub trait WriteHeaders {
     fn write_headers(&self, headers: &mut http::header::HeaderMap);
 }
 
 const NO_CACHE: HeaderValue = HeaderValue::from_static("max-age=0, private, must-revalidate");
+const VARY: HeaderValue = HeaderValue::from_static("accept-encoding");
+
+/// Embedded files are hashed into their names by trunk, except for `index.html`.
+fn cache_control(mime: &HeaderValue) -> HeaderValue {
+    if mime == "text/html" {
+        NO_CACHE
+    } else {
+        HeaderValue::from_static("public, max-age=31536000, immutable")
+    }
+}
+
+fn write_validators(headers: &mut HeaderMap, validators: &Validators) {
+    headers.insert(header::ETAG, validators.etag.clone());
+    if let Some(last_modified) = validators.last_modified_header() {
+        headers.insert(header::LAST_MODIFIED, last_modified);
+    }
+}
 
 impl<C: Clone + WriteHeaders> Future for ResponseFuture<C> {
     type Output = Result<Response<Body>, Infallible>;
 
     fn poll(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Self::Output> {
-        match &self.inner {
+        match std::mem::replace(&mut self.get_mut().inner, Inner::Invalid) {
             Inner::Valid(TheOutput::Version(v)) => {
-                let mut res = version(v.clone()).into_response();
+                let mut res = version(v).into_response();
                 res.headers_mut().insert(header::CACHE_CONTROL, NO_CACHE);
+                res.headers_mut().insert(header::VARY, VARY);
+                Poll::Ready(Ok(res))
+            }
+            #[cfg(feature = "compression")]
+            Inner::Valid(TheOutput::NotAcceptable) => {
+                let res = Response::builder()
+                    .status(StatusCode::NOT_ACCEPTABLE)
+                    .header(header::VARY, VARY)
+                    .header(header::CACHE_CONTROL, NO_CACHE)
+                    .body(Body::empty())
+                    .unwrap();
+
+                Poll::Ready(Ok(res))
+            }
+            Inner::Valid(TheOutput::NotFound) => {
+                // not even the 404 is worth keeping, the file may be there after the next deploy
+                let res = Response::builder()
+                    .status(StatusCode::NOT_FOUND)
+                    .header(header::VARY, VARY)
+                    .header(header::CACHE_CONTROL, HeaderValue::from_static("no-store"))
+                    .body(Body::empty())
+                    .unwrap();
+
+                Poll::Ready(Ok(res))
+            }
+            Inner::Valid(TheOutput::NotModified { validators, mime }) => {
+                let mut res = Response::builder()
+                    .status(StatusCode::NOT_MODIFIED)
+                    .header(header::VARY, VARY)
+                    .header(header::CACHE_CONTROL, cache_control(&mime))
+                    .body(Body::empty())
+                    .unwrap();
+                write_validators(res.headers_mut(), &validators);
+
                 Poll::Ready(Ok(res))
             }
             Inner::Valid(TheOutput::Other {
-                bytes,
+                status,
+                headers: rendered_headers,
+                body,
                 mime,
                 encoding,
+                validators,
                 cookie_jars,
             }) => {
-                let mut res = Body::from(bytes.clone()).into_response();
+                let mut res = body.into_response();
+                *res.status_mut() = status;
+                res.headers_mut().extend(rendered_headers);
                 cookie_jars.write_headers(res.headers_mut());
 
                 let headers = res.headers_mut();
-                headers.insert(header::CONTENT_TYPE, mime.clone());
-                headers.insert(header::CONTENT_ENCODING, encoding.into_header_value());
-                if mime == "text/html" {
-                    headers.insert(header::CACHE_CONTROL, NO_CACHE);
-                } else {
-                    headers.insert(
-                        header::CACHE_CONTROL,
-                        HeaderValue::from_static("public, max-age=31536000, immutable"),
-                    );
+                headers.remove(header::CONTENT_ENCODING);
+                if encoding != Encoding::Identity {
+                    headers.insert(header::CONTENT_ENCODING, encoding.into_header_value());
+                }
+                headers.append(header::VARY, VARY);
+                if let Some(validators) = &validators {
+                    write_validators(headers, validators);
                 }
+                headers
+                    .entry(header::CACHE_CONTROL)
+                    .or_insert(cache_control(&mime));
+                headers.entry(header::CONTENT_TYPE).or_insert(mime);
 
                 Poll::Ready(Ok(res))
             }
             Inner::Invalid => {
                 let res = Response::builder()
                     .status(StatusCode::BAD_REQUEST)
+                    .header(header::VARY, VARY)
                     .body(Body::empty())
                     .unwrap();
 
@@ -0,0 +1,68 @@
+//! Requests that can only be for an embedded file, answered with a `404` when there is none
+//! instead of a rendered page.
+
+/// Extensions of the files trunk writes, and of common assets. Data formats like `json` or
+/// `xml` are left out, as pages may well be rendered under such paths.
+const DEFAULT_EXTENSIONS: &[&str] = &[
+    "js", "mjs", "wasm", "css", "map", "png", "jpg", "jpeg", "gif", "svg", "ico", "webp", "avif",
+    "woff", "woff2", "ttf", "otf", "eot", "mp3", "mp4", "webm", "pdf", "zip",
+];
+
+/// Which paths never fall back to [`Process::render`](crate::Process::render), see
+/// [`ServeYew::static_paths`](crate::ServeYew::static_paths).
+///
+/// Without these, a stale tab asking for `/frontend-oldhash.js` gets `index.html` with a `200`,
+/// which crawlers and caches are happy to keep as JavaScript. By default, everything under
+/// `assets/` and every path ending in a known static extension is a static path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StaticPaths {
+    prefixes: Vec<String>,
+    extensions: Vec<String>,
+}
+
+impl Default for StaticPaths {
+    fn default() -> Self {
+        Self {
+            prefixes: vec!["assets/".to_owned()],
+            extensions: DEFAULT_EXTENSIONS.iter().map(|&e| e.to_owned()).collect(),
+        }
+    }
+}
+
+impl StaticPaths {
+    /// Every path falls back to rendering, like before there were static paths.
+    pub fn none() -> Self {
+        Self {
+            prefixes: Vec::new(),
+            extensions: Vec::new(),
+        }
+    }
+
+    /// Paths starting with `prefix`, e.g. `static/`, without the leading `/`.
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefixes.push(prefix.into());
+        self
+    }
+
+    /// Paths ending in `.{extension}`, compared case-insensitively.
+    pub fn extension(mut self, extension: impl Into<String>) -> Self {
+        let extension: String = extension.into();
+        self.extensions
+            .push(extension.trim_start_matches('.').to_ascii_lowercase());
+        self
+    }
+
+    /// `path` is relative to the root, without the leading `/`.
+    pub(crate) fn matches(&self, path: &str) -> bool {
+        if self.prefixes.iter().any(|p| path.starts_with(p.as_str())) {
+            return true;
+        }
+
+        let file_name = path.rsplit('/').next().unwrap_or(path);
+        file_name.rsplit_once('.').is_some_and(|(_, extension)| {
+            self.extensions
+                .iter()
+                .any(|e| e.eq_ignore_ascii_case(extension))
+        })
+    }
+}